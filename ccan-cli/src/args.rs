@@ -1,16 +1,30 @@
-use ccan::bettergit::{BetterGitOpt, CommitFilteringOpt, DateGrouping, FileFilteringOpt};
+use anyhow::Result;
+use ccan::bettergit::{BetterGitOpt, ChangeKind, DateGrouping, FileFilteringOpt, DEFAULT_EXCLUDE_GLOBS};
+use ccan::changes::ChurnWeighting;
 use ccan::cochanges::CoChangesOpt;
+use ccan::components::ComponentGrouping;
+use ccan::evaluate::EvaluationOpt;
+use ccan::gitattributes::AttributesScope;
+use ccan::graph::{EdgeWeight, GraphFormat, GraphOpt};
+use ccan::hotspots::{HotspotMetric, HotspotOpt};
 use ccan::model::ModelTypes;
-use ccan::predict::PredictionOpt;
+use ccan::architecture::ArchitectureOpt;
+use ccan::communities::{CommunityAlgorithm, CommunityOpt};
+use ccan::coordination::CoordinationOpt;
+use ccan::dependencies::DependencyOpt;
+use ccan::ownership::{OwnershipMeasure, OwnershipOpt};
+use ccan::predict::{MarkdownOpt, PredictionOpt};
+use ccan::symbols::Granularity;
+use ccan::transactions::{TransactionGrouping, DEFAULT_ISSUE_REGEX};
 use ccan::Options;
-use chrono::{DateTime, Days, NaiveDate, TimeZone, Utc};
-use clap::{arg, Parser};
-use log::LevelFilter;
-
-use std::ops::{Add, Sub};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use clap::{Args, Parser, Subcommand};
+use log::{info, LevelFilter};
 
 use std::str::FromStr;
 
+const COMPONENTS_HELP: &str = "Aggregate files into components: the first N directories, globs mapped to components in a file, or Cargo/Maven/npm packages [default: files] [possible values: files, dirs:N, mapping:FILE, packages]";
+
 #[derive(Parser, Debug)]
 #[command(
     author,
@@ -21,71 +35,304 @@ use std::str::FromStr;
 #[command(
     help_template = "{about-section} Version: {version} \n by {author} \n {usage-heading} {usage} \n {all-args} {tab}"
 )]
-pub struct Args {
-    #[arg(short, long, required = true, help = "The git repository")]
-    pub repository: String,
-    #[arg(short, long, required = true, help = "The branch to mine commits from")]
-    pub branch: String,
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
     #[arg(
         short,
         long,
-        default_value = "5",
-        help = "Ignore files with fewer total changes than given"
+        global = true,
+        default_value = "Debug",
+        help = "Logging level [possible values: Off, Error, Warn, Info, Debug, Trace]"
     )]
-    pub changes_min: u32,
+    pub log_level: LevelFilter,
     #[arg(
-        short,
         long,
-        default_value = "5",
-        help = "Remove file pairs with co-change frequency lower than given"
+        global = true,
+        help = "TOML or YAML configuration file, flags override its values [default: ccan.toml in the repository root, if any]"
     )]
-    pub freq_min: u32,
+    pub config: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum Command {
+    #[command(about = "Mine, analyse and predict in one go")]
+    Run(RunArgs),
+    #[command(about = "Extract the change history of a repository")]
+    Mine(MineArgs),
+    #[command(about = "Calculate co-changes from a mined change history")]
+    Analyze(AnalyzeArgs),
+    #[command(about = "Predict ripple changes using an analysed model")]
+    Predict(PredictArgs),
+    #[command(about = "Backtest the prediction on a mined change history")]
+    Evaluate(EvaluateArgs),
+    #[command(about = "Convert an analysed model to graphs or reports")]
+    Export(ExportArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct GitArgs {
+    #[arg(short, long, required = true, help = "The git repository")]
+    pub repository: String,
+    #[arg(short, long, help = "The branch to mine commits from [default: HEAD]")]
+    pub branch: Option<String>,
+    #[arg(long = "ref", help = "Also mine the given revision or ref glob, e.g. refs/heads/release/*, can be repeated")]
+    pub refs: Vec<String>,
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", help = "Also mine all refs, commits reachable from several refs are mined once")]
+    pub all: Option<bool>,
+    #[arg(long, help = "Select commits until given date (YYYY-MM-DD) [default: 9999-1-1]")]
+    pub until: Option<NaiveDate>,
+    #[arg(long, help = "Select commits after given date (YYYY-MM-DD) [default: 1900-1-1]")]
+    pub since: Option<NaiveDate>,
+    #[arg(long, help = "Mine the given revision range instead of the branch, e.g. v2.0..v3.0, v3.0..main or ^v1.0, can be repeated")]
+    pub range: Vec<String>,
+    #[arg(short, long, value_enum, help = "Binning strategy for commits. None is more precise, but slower. [default: none] [possible values: none, daily, weekly, monthly]", value_parser = DateGrouping::from_str)]
+    pub date_binning: Option<DateGrouping>,
+    #[arg(long, help = "How commits are grouped into transactions: by date bin, by the issue key in their message, or by author sessions [default: dates] [possible values: dates, issues, sessions]", value_parser = TransactionGrouping::from_str)]
+    pub transactions: Option<TransactionGrouping>,
+    #[arg(long, help = format!("Regex extracting the issue key from commit messages when grouping by issues [default: {}]", DEFAULT_ISSUE_REGEX))]
+    pub issue_regex: Option<String>,
+    #[arg(long, help = "Maximum gap between consecutive commits of an author in the same session, e.g. 30m or 2h [default: 30m]")]
+    pub session_gap: Option<String>,
+    #[arg(long, help = "Regex to include matching files (case insensitive), can be repeated [default: .*]")]
+    pub include_regex: Vec<String>,
+    #[arg(long, help = "Regex to exclude matching files (case insensitive), can be repeated")]
+    pub exclude_regex: Vec<String>,
+    #[arg(long, help = "Gitignore-style glob to include matching files (case insensitive), can be repeated")]
+    pub include_glob: Vec<String>,
+    #[arg(long, help = format!("Gitignore-style glob to exclude matching files (case insensitive), can be repeated [default: {}]", DEFAULT_EXCLUDE_GLOBS.join(" ")))]
+    pub exclude_glob: Vec<String>,
+    #[arg(long, help = "Exclude files marked as linguist-generated, linguist-vendored or -diff in .gitattributes at each mined commit or at the newest one [default: none] [possible values: none, head, commit]", value_parser = AttributesScope::from_str)]
+    pub gitattributes: Option<AttributesScope>,
+    #[arg(long, help = "Regex matched against 'Name <email>' to only include commits by matching authors, can be repeated")]
+    pub include_author: Vec<String>,
+    #[arg(long, help = "Regex matched against 'Name <email>' to exclude commits by matching authors, can be repeated")]
+    pub exclude_author: Vec<String>,
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", help = "Exclude commits by common bots, such as Dependabot and Renovate")]
+    pub exclude_bots: Option<bool>,
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", help = "Resolve author identities using the repository's .mailmap")]
+    pub mailmap: Option<bool>,
+    #[arg(long, help = "Only include commits whose message matches the regex (case insensitive)")]
+    pub message_regex: Option<String>,
+    #[arg(long, help = "Exclude commits whose message matches the regex (case insensitive)")]
+    pub exclude_message_regex: Option<String>,
+    #[arg(long, value_delimiter = ',', help = "Only include conventional commits of the given types, e.g. fix,perf")]
+    pub commit_type: Vec<String>,
+    #[arg(long, value_delimiter = ',', help = "Exclude conventional commits of the given types, e.g. chore,style")]
+    pub exclude_commit_type: Vec<String>,
+    #[arg(long, value_delimiter = ',', help = "Only include conventional commits with the given scopes")]
+    pub commit_scope: Vec<String>,
+    #[arg(long, value_delimiter = ',', help = "Exclude conventional commits with the given scopes")]
+    pub exclude_commit_scope: Vec<String>,
+    #[arg(long, help = "Record co-changes between files, or between the Rust, Java, Python and TypeScript functions, methods and types changed in them [default: files] [possible values: files, symbols]", value_parser = Granularity::from_str)]
+    pub granularity: Option<Granularity>,
+    #[arg(long, help = "Weight each change by the lines inserted and deleted: not at all, by their number, its logarithm or their number up to N; the Bayesian model counts transactions regardless [default: none] [possible values: none, raw, log, capped:N]", value_parser = ChurnWeighting::from_str)]
+    pub churn: Option<ChurnWeighting>,
+    #[arg(long, value_delimiter = ',', help = "Ignore files being changed in the given ways when counting co-changes, e.g. added,deleted [possible values: added, modified, deleted, renamed]", value_parser = ChangeKind::from_str)]
+    pub exclude_change: Vec<ChangeKind>,
+}
+
+#[derive(Args, Debug)]
+pub struct CoChangeArgs {
+    #[arg(short, long, help = "Ignore files with fewer total changes than given [default: 5]")]
+    pub changes_min: Option<u32>,
+    #[arg(short, long, help = "Remove file pairs with co-change frequency lower than given [default: 5]")]
+    pub freq_min: Option<u32>,
+    #[arg(short, long, value_enum, help = "Impact probability calculation algorithm. [default: naive] [possible values: naive, bayes, mixed, nop]", value_parser = ModelTypes::from_str)]
+    pub algorithm: Option<ModelTypes>,
+}
+
+#[derive(Args, Debug)]
+pub struct PredictionArgs {
+    #[arg(long, help = "Predict changes based on files changed since the given date (YYYY-MM-DD) [default: 30 days ago]")]
+    predict_since: Option<NaiveDate>,
+    #[arg(long, help = "Predict changes based on files changed until the given date (YYYY-MM-DD) [default: tomorrow]")]
+    predict_until: Option<NaiveDate>,
     #[arg(
         long,
-        default_value = "9999-1-1",
-        help = "Select commits until given date (YYYY-MM-DD)"
+        value_delimiter = ',',
+        help = "Predict changes based on the given files instead of the files changed in the prediction period"
     )]
-    pub until: NaiveDate,
+    pub changed_files: Vec<String>,
     #[arg(
         long,
-        default_value = "1900-1-1",
-        help = "Select commits after given date (YYYY-MM-DD)"
+        default_value = "false",
+        help = "Write the files missing from the change as a Markdown summary"
     )]
-    pub since: NaiveDate,
-    #[arg(short, long, value_enum, default_value = "none", help = "Binning strategy for commits. None is more precise, but slower. [possible values: none, daily, weekly, monthly]", value_parser = DateGrouping::from_str)]
-    pub date_binning: DateGrouping,
-    #[arg(short, long, value_enum, default_value = "naive", help = "Impact probability calculation algorithm. [possible values: naive, bayes, mixed, nop]", value_parser = ModelTypes::from_str)]
-    pub algorithm: ModelTypes,
+    pub markdown: bool,
     #[arg(
         long,
-        default_value = ".*",
-        help = "Regex to include matching files (case insensitive)"
+        default_value = "0.1",
+        help = "Only list files with a change probability of at least given in the Markdown summary"
     )]
-    pub include_regex: String,
+    pub markdown_min_probability: f64,
+}
+
+#[derive(Args, Debug)]
+pub struct GraphArgs {
+    #[arg(long, help = "Export the co-change graph in the given format, can be repeated [possible values: graphml, gexf, dot]", value_parser = GraphFormat::from_str)]
+    pub graph_format: Vec<GraphFormat>,
+    #[arg(long, default_value = "probs", help = "Co-change matrix used as edge weight in graph exports [possible values: probs, freqs]", value_parser = EdgeWeight::from_str)]
+    pub graph_weight: EdgeWeight,
     #[arg(
         long,
-        default_value = r".*(json|lock|sh|proto|bat|md|txt|yaml|yml|Dockerfile|mod|sum|.DS_Store|.gitignore)$",
-        help = "Regex to exclude matching files (case insensitive)"
+        default_value = "0",
+        help = "Drop graph edges with a weight lower than given"
     )]
-    pub exclude_regex: String,
+    pub graph_min_weight: f64,
     #[arg(
         long,
         default_value = "false",
+        help = "Cluster graph nodes by their directory"
+    )]
+    pub graph_cluster_dirs: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct HotspotArgs {
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        help = "Rank the files changing often that are large or complex at the head of the mined history"
+    )]
+    pub hotspots: Option<bool>,
+    #[arg(long, help = "Size or complexity measure multiplied by the changes of a file to rank hotspots [default: loc] [possible values: loc, indentation, cyclomatic]", value_parser = HotspotMetric::from_str)]
+    pub hotspot_metric: Option<HotspotMetric>,
+    #[arg(long, help = "Number of periods the history is split into to compute hotspot trends [default: 4]")]
+    pub hotspot_periods: Option<usize>,
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        help = "Compute the cyclomatic complexity of Rust, Java, Python and TypeScript hotspots"
+    )]
+    pub cyclomatic: Option<bool>,
+}
+
+#[derive(Args, Debug)]
+pub struct OwnershipArgs {
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        help = "Compute the authors' share of each file and directory, bus factors and knowledge loss"
+    )]
+    pub ownership: Option<bool>,
+    #[arg(long, help = "Measure contributions to a file by commits or by lines changed [default: commits] [possible values: commits, churn]", value_parser = OwnershipMeasure::from_str)]
+    pub ownership_by: Option<OwnershipMeasure>,
+    #[arg(long, help = "Consider authors without commits since the given date (YYYY-MM-DD) as gone [default: 180 days before the last commit]")]
+    pub inactive_since: Option<NaiveDate>,
+    #[arg(long, help = "Flag co-changing files with different owners from the given probability [default: 0.5]")]
+    pub crossing_min_probability: Option<f64>,
+}
+
+#[derive(Args, Debug)]
+pub struct CoordinationArgs {
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        help = "Link developers changing co-changing files and flag coupled files whose authors never meet"
+    )]
+    pub coordination: Option<bool>,
+    #[arg(long, help = "Flag co-changing files sharing no author from the given probability [default: 0.5]")]
+    pub gap_min_probability: Option<f64>,
+}
+
+#[derive(Args, Debug)]
+pub struct CommunityArgs {
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        help = "Cluster the co-change graph and compare the clusters with the directories"
+    )]
+    pub communities: Option<bool>,
+    #[arg(long, help = "Clustering algorithm [default: louvain] [possible values: louvain, propagation]", value_parser = CommunityAlgorithm::from_str)]
+    pub community_algorithm: Option<CommunityAlgorithm>,
+    #[arg(long, help = "Co-change matrix used as edge weight for clustering [default: probs] [possible values: probs, freqs]", value_parser = EdgeWeight::from_str)]
+    pub community_weight: Option<EdgeWeight>,
+    #[arg(long, help = "Ignore edges with a weight lower than given when clustering [default: 0]")]
+    pub community_min_weight: Option<f64>,
+}
+
+#[derive(Args, Debug)]
+pub struct ArchitectureArgs {
+    #[arg(long, help = "Report co-changes between layers whose coupling is not allowed by the given TOML file")]
+    pub architecture: Option<String>,
+    #[arg(long, help = "Report couplings between layers from the given co-change probability [default: 0.2]")]
+    pub violation_min_probability: Option<f64>,
+    #[arg(long, help = "Days after which the score of a violation halves if the layers did not change together since [default: 90]")]
+    pub violation_half_life: Option<i64>,
+}
+
+#[derive(Args, Debug)]
+pub struct DependencyArgs {
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        help = "Report co-changing files without imports from one to the other at the head of the branch"
+    )]
+    pub hidden_dependencies: Option<bool>,
+    #[arg(long, help = "Report pairs without imports from the given co-change probability [default: 0.5]")]
+    pub hidden_min_probability: Option<f64>,
+}
+
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub git: GitArgs,
+    #[command(flatten)]
+    pub cc: CoChangeArgs,
+    #[command(flatten)]
+    pub prediction: PredictionArgs,
+    #[command(flatten)]
+    pub graph: GraphArgs,
+    #[arg(long, help = COMPONENTS_HELP, value_parser = ComponentGrouping::from_str)]
+    pub components: Option<ComponentGrouping>,
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
         help = "Do not perform a prediction using the cochange probability"
     )]
-    pub skip_predict: bool,
+    pub skip_predict: Option<bool>,
     #[arg(
         long,
-        default_value_t = Utc::now().sub(Days::new(30)).date_naive(),
-        help = "Predict changes based on files changed since the given date (YYYY-MM-DD)"
+        default_value = "false",
+        help = "Write a self-contained HTML report of the analysis"
     )]
-    predict_since: NaiveDate,
+    pub report: bool,
+    #[command(flatten)]
+    pub hotspots: HotspotArgs,
+    #[command(flatten)]
+    pub ownership: OwnershipArgs,
+    #[command(flatten)]
+    pub coordination: CoordinationArgs,
+    #[command(flatten)]
+    pub communities: CommunityArgs,
+    #[command(flatten)]
+    pub architecture: ArchitectureArgs,
+    #[command(flatten)]
+    pub dependencies: DependencyArgs,
     #[arg(
         long,
-        default_value_t = Utc::now().add(Days::new(1)).date_naive(),
-        help = "Predict changes based on files changed until the given date (YYYY-MM-DD)"
+        default_value = "false",
+        help = "Run one analysis per pair of consecutive tags, each written to its own directory"
     )]
-    predict_until: NaiveDate,
+    pub per_tag_pair: bool,
+    #[arg(long, requires = "per_tag_pair", help = "Only use the tags matching the glob, e.g. v*")]
+    pub tag_pattern: Option<String>,
     #[arg(
         short,
         long,
@@ -93,56 +340,408 @@ pub struct Args {
         help = "Directory to write output files to"
     )]
     pub output_dir: String,
+}
+
+#[derive(Args, Debug)]
+pub struct MineArgs {
+    #[command(flatten)]
+    pub git: GitArgs,
     #[arg(
         short,
         long,
-        default_value = "Debug",
-        help = "Logging level [possible values: Off, Error, Warn, Info, Debug, Trace]"
+        required = true,
+        help = "Directory to write the change history to"
     )]
-    pub log_level: LevelFilter,
+    pub output_dir: String,
+}
+
+#[derive(Args, Debug)]
+pub struct AnalyzeArgs {
+    #[arg(long, required = true, help = "Change history written by the mine command")]
+    pub changes: String,
+    #[command(flatten)]
+    pub cc: CoChangeArgs,
+    #[arg(long, help = COMPONENTS_HELP, value_parser = ComponentGrouping::from_str)]
+    pub components: Option<ComponentGrouping>,
+    #[arg(short, long, help = "The git repository to discover packages in, required with --components packages")]
+    pub repository: Option<String>,
+    #[arg(
+        short,
+        long,
+        required = true,
+        help = "Directory to write the model to"
+    )]
+    pub output_dir: String,
+}
+
+#[derive(Args, Debug)]
+pub struct PredictArgs {
+    #[arg(long, required = true, help = "Model file written by the analyze command")]
+    pub model: String,
+    #[arg(long, help = "Change history written by the mine command, required unless --changed-files is given")]
+    pub changes: Option<String>,
+    #[arg(short, long, value_enum, help = "Impact probability calculation algorithm, must match the model. [default: the model's algorithm] [possible values: naive, bayes, mixed, nop]", value_parser = ModelTypes::from_str)]
+    pub algorithm: Option<ModelTypes>,
+    #[command(flatten)]
+    pub prediction: PredictionArgs,
+    #[arg(
+        short,
+        long,
+        required = true,
+        help = "Directory to write the predictions to"
+    )]
+    pub output_dir: String,
+}
+
+#[derive(Args, Debug)]
+pub struct EvaluateArgs {
+    #[arg(long, required = true, help = "Change history written by the mine command")]
+    pub changes: String,
+    #[command(flatten)]
+    pub cc: CoChangeArgs,
+    #[arg(long, help = "Train on transactions before the given date and test on the others (YYYY-MM-DD) [default: date of the 80th percentile transaction]")]
+    pub split: Option<NaiveDate>,
+    #[arg(long, default_value = "10", help = "Number of predicted files to evaluate per query")]
+    pub top_k: usize,
+    #[arg(
+        short,
+        long,
+        required = true,
+        help = "Directory to write the evaluation to"
+    )]
+    pub output_dir: String,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    #[arg(long, required = true, help = "Model file written by the analyze command")]
+    pub model: String,
+    #[arg(long, required = true, help = "Change history written by the mine command")]
+    pub changes: String,
+    #[command(flatten)]
+    pub graph: GraphArgs,
+    #[arg(
+        long,
+        default_value = "false",
+        help = "Write a self-contained HTML report of the model"
+    )]
+    pub report: bool,
+    #[arg(
+        short,
+        long,
+        required = true,
+        help = "Directory to write the exported files to"
+    )]
+    pub output_dir: String,
 }
 
-impl Args {
-    pub fn to_options(self) -> Options {
-        let since = Args::to_datetime_0(&self.since);
-        let until = Args::to_datetime_23(&self.until);
-        let predict_since = Args::to_datetime_0(&self.predict_since);
-        let predict_until = Args::to_datetime_23(&self.predict_until);
-
-        let file_filters = FileFilteringOpt::new(
-            &[self.exclude_regex.as_str()],
-            &[self.include_regex.as_str()],
-        );
-        Options {
-            repository: self.repository,
-            cc_opts: CoChangesOpt {
-                freq_min: self.freq_min,
-                changes_min: self.changes_min,
-                algorithm: self.algorithm,
-            },
-            git_opts: BetterGitOpt {
-                file_filters,
-                commit_filters: CommitFilteringOpt {
-                    branch: self.branch,
-                    binning: self.date_binning,
-                    since,
-                    until,
-                },
-            },
-            pred_opts: PredictionOpt {
-                skip: self.skip_predict,
-                since_changes: predict_since,
-                until_changes: predict_until,
-                algorithm: self.algorithm,
-            },
-        }
-    }
-
-    fn to_datetime_0(naive_date: &NaiveDate) -> DateTime<Utc> {
-        Utc::from_utc_datetime(&Utc, &naive_date.and_hms_opt(0, 0, 0).unwrap())
-    }
-
-    fn to_datetime_23(naive_date: &NaiveDate) -> DateTime<Utc> {
-        Utc::from_utc_datetime(&Utc, &naive_date.and_hms_opt(23, 59, 59).unwrap())
+impl RunArgs {
+    pub fn to_options(&self, config: Option<&str>) -> Result<Options> {
+        let mut opts = load_options(config, Some(&self.git.repository))?;
+        opts.repository = self.git.repository.clone();
+        self.git.apply(&mut opts.git_opts)?;
+        self.cc.apply(&mut opts.cc_opts);
+        self.prediction.apply(&mut opts.pred_opts);
+        if let Some(skip) = self.skip_predict {
+            opts.pred_opts.skip = skip;
+        }
+        if let Some(components) = &self.components {
+            opts.components = components.clone();
+        }
+        self.hotspots.apply(&mut opts.hotspots);
+        self.ownership.apply(&mut opts.ownership);
+        self.coordination.apply(&mut opts.coordination);
+        self.communities.apply(&mut opts.communities);
+        self.architecture.apply(&mut opts.architecture);
+        self.dependencies.apply(&mut opts.dependencies);
+        opts.pred_opts.algorithm = opts.cc_opts.algorithm;
+        Ok(opts)
+    }
+}
+
+impl MineArgs {
+    pub fn to_options(&self, config: Option<&str>) -> Result<BetterGitOpt> {
+        let mut opts = load_options(config, Some(&self.git.repository))?.git_opts;
+        self.git.apply(&mut opts)?;
+        Ok(opts)
+    }
+}
+
+/// Loads the given configuration file, or the one found in the repository root, if any.
+pub fn load_options(config: Option<&str>, repository: Option<&str>) -> Result<Options> {
+    let path = config.map(String::from)
+        .or_else(|| repository.and_then(Options::find_config));
+    match path {
+        Some(path) => {
+            info!("Reading configuration from {}", path);
+            Options::from_file(&path)
+        }
+        None => Ok(Options::default()),
+    }
+}
+
+impl GitArgs {
+    pub fn apply(&self, opts: &mut BetterGitOpt) -> Result<()> {
+        let commit_filters = &mut opts.commit_filters;
+        if let Some(branch) = &self.branch {
+            commit_filters.branch = branch.clone();
+        }
+        if !self.refs.is_empty() {
+            commit_filters.refs = self.refs.clone();
+        }
+        if let Some(all) = self.all {
+            commit_filters.all_refs = all;
+        }
+        if !self.range.is_empty() {
+            commit_filters.ranges = self.range.clone();
+        }
+        if let Some(since) = &self.since {
+            commit_filters.since = to_datetime_0(since);
+        }
+        if let Some(until) = &self.until {
+            commit_filters.until = to_datetime_23(until);
+        }
+        if let Some(binning) = &self.date_binning {
+            commit_filters.binning = binning.clone();
+        }
+        if let Some(transactions) = self.transactions {
+            commit_filters.transactions = transactions;
+        }
+        if let Some(issue_regex) = &self.issue_regex {
+            commit_filters.issue_regex = issue_regex.clone();
+        }
+        if let Some(session_gap) = &self.session_gap {
+            commit_filters.session_gap = session_gap.clone();
+        }
+        if !self.include_author.is_empty() {
+            commit_filters.include_authors = self.include_author.clone();
+        }
+        if !self.exclude_author.is_empty() {
+            commit_filters.exclude_authors = self.exclude_author.clone();
+        }
+        if self.message_regex.is_some() {
+            commit_filters.message_regex = self.message_regex.clone();
+        }
+        if self.exclude_message_regex.is_some() {
+            commit_filters.exclude_message_regex = self.exclude_message_regex.clone();
+        }
+        if !self.commit_type.is_empty() {
+            commit_filters.include_types = self.commit_type.clone();
+        }
+        if !self.exclude_commit_type.is_empty() {
+            commit_filters.exclude_types = self.exclude_commit_type.clone();
+        }
+        if !self.commit_scope.is_empty() {
+            commit_filters.include_scopes = self.commit_scope.clone();
+        }
+        if !self.exclude_commit_scope.is_empty() {
+            commit_filters.exclude_scopes = self.exclude_commit_scope.clone();
+        }
+        if let Some(exclude_bots) = self.exclude_bots {
+            commit_filters.exclude_bots = exclude_bots;
+        }
+        if let Some(mailmap) = self.mailmap {
+            commit_filters.mailmap = mailmap;
+        }
+        if let Some(granularity) = self.granularity {
+            opts.granularity = granularity;
+        }
+        if let Some(churn) = self.churn {
+            opts.churn = churn;
+        }
+        if !self.exclude_change.is_empty() {
+            opts.exclude_changes = self.exclude_change.clone();
+        }
+        let file_filters = &opts.file_filters;
+        let (mut exclude, mut include) = (Vec::new(), Vec::new());
+        if self.exclude_regex.is_empty() && self.exclude_glob.is_empty() {
+            exclude.push(file_filters.exclude_paths.as_str());
+        }
+        if self.include_regex.is_empty() && self.include_glob.is_empty() {
+            include.push(file_filters.include_paths.as_str());
+        }
+        exclude.extend(self.exclude_regex.iter().map(String::as_str));
+        include.extend(self.include_regex.iter().map(String::as_str));
+        let attributes = self.gitattributes.unwrap_or(file_filters.attributes);
+        opts.file_filters = FileFilteringOpt::with_globs(
+            &exclude,
+            &self.exclude_glob.iter().map(String::as_str).collect::<Vec<_>>(),
+            &include,
+            &self.include_glob.iter().map(String::as_str).collect::<Vec<_>>(),
+        )?;
+        opts.file_filters.attributes = attributes;
+        Ok(())
+    }
+}
+
+impl HotspotArgs {
+    pub fn apply(&self, opts: &mut HotspotOpt) {
+        if let Some(enabled) = self.hotspots {
+            opts.enabled = enabled;
+        }
+        if let Some(cyclomatic) = self.cyclomatic {
+            opts.cyclomatic = cyclomatic;
+        }
+        if let Some(metric) = self.hotspot_metric {
+            opts.metric = metric;
+        }
+        if let Some(periods) = self.hotspot_periods {
+            opts.periods = periods;
+        }
+    }
+}
+
+impl OwnershipArgs {
+    pub fn apply(&self, opts: &mut OwnershipOpt) {
+        if let Some(enabled) = self.ownership {
+            opts.enabled = enabled;
+        }
+        if let Some(measure) = self.ownership_by {
+            opts.measure = measure;
+        }
+        if let Some(since) = &self.inactive_since {
+            opts.inactive_since = Some(to_datetime_0(since));
+        }
+        if let Some(min_probability) = self.crossing_min_probability {
+            opts.min_probability = min_probability;
+        }
+    }
+}
+
+impl CoordinationArgs {
+    pub fn apply(&self, opts: &mut CoordinationOpt) {
+        if let Some(enabled) = self.coordination {
+            opts.enabled = enabled;
+        }
+        if let Some(min_probability) = self.gap_min_probability {
+            opts.min_probability = min_probability;
+        }
+    }
+}
+
+impl CommunityArgs {
+    pub fn apply(&self, opts: &mut CommunityOpt) {
+        if let Some(enabled) = self.communities {
+            opts.enabled = enabled;
+        }
+        if let Some(algorithm) = self.community_algorithm {
+            opts.algorithm = algorithm;
+        }
+        if let Some(weight) = self.community_weight {
+            opts.weight = weight;
+        }
+        if let Some(min_weight) = self.community_min_weight {
+            opts.min_weight = min_weight;
+        }
+    }
+}
+
+impl ArchitectureArgs {
+    pub fn apply(&self, opts: &mut ArchitectureOpt) {
+        if let Some(file) = &self.architecture {
+            opts.file = Some(file.clone());
+        }
+        if let Some(min_probability) = self.violation_min_probability {
+            opts.min_probability = min_probability;
+        }
+        if let Some(half_life) = self.violation_half_life {
+            opts.half_life_days = half_life;
+        }
+    }
+}
+
+impl DependencyArgs {
+    pub fn apply(&self, opts: &mut DependencyOpt) {
+        if let Some(enabled) = self.hidden_dependencies {
+            opts.enabled = enabled;
+        }
+        if let Some(min_probability) = self.hidden_min_probability {
+            opts.min_probability = min_probability;
+        }
+    }
+}
+
+impl CoChangeArgs {
+    pub fn apply(&self, opts: &mut CoChangesOpt) {
+        if let Some(changes_min) = self.changes_min {
+            opts.changes_min = changes_min;
+        }
+        if let Some(freq_min) = self.freq_min {
+            opts.freq_min = freq_min;
+        }
+        if let Some(algorithm) = self.algorithm {
+            opts.algorithm = algorithm;
+        }
+    }
+}
+
+impl PredictionArgs {
+    pub fn apply(&self, opts: &mut PredictionOpt) {
+        if let Some(since) = &self.predict_since {
+            opts.since_changes = to_datetime_0(since);
+        }
+        if let Some(until) = &self.predict_until {
+            opts.until_changes = to_datetime_23(until);
+        }
+        if !self.changed_files.is_empty() {
+            opts.changed_files = self.changed_files.clone();
+        }
+    }
+
+    pub fn markdown_opts(&self) -> MarkdownOpt {
+        MarkdownOpt {
+            min_probability: self.markdown_min_probability,
+            ..MarkdownOpt::default()
+        }
+    }
+}
+
+impl GraphArgs {
+    pub fn to_options(&self) -> GraphOpt {
+        GraphOpt {
+            weight: self.graph_weight,
+            min_weight: self.graph_min_weight,
+            cluster_dirs: self.graph_cluster_dirs,
+        }
+    }
+}
+
+impl EvaluateArgs {
+    pub fn to_options(&self, default_split: DateTime<Utc>) -> EvaluationOpt {
+        EvaluationOpt {
+            split: self.split.as_ref().map_or(default_split, to_datetime_0),
+            top_k: self.top_k,
+        }
+    }
+}
+
+fn to_datetime_0(naive_date: &NaiveDate) -> DateTime<Utc> {
+    Utc::from_utc_datetime(&Utc, &naive_date.and_hms_opt(0, 0, 0).unwrap())
+}
+
+fn to_datetime_23(naive_date: &NaiveDate) -> DateTime<Utc> {
+    Utc::from_utc_datetime(&Utc, &naive_date.and_hms_opt(23, 59, 59).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use ccan::hotspots::HotspotOpt;
+    use clap::Parser;
+
+    use crate::args::{Cli, Command};
+
+    #[test]
+    fn test_flags_override_configuration() {
+        let cli = Cli::try_parse_from(["ccan-rs", "run", "-r", ".", "-o", "out", "--hotspots=false", "--mailmap"]).unwrap();
+        let run = match cli.command {
+            Command::Run(run) => run,
+            _ => panic!("not a run command"),
+        };
+        let mut hotspots = HotspotOpt { enabled: true, cyclomatic: true, ..HotspotOpt::default() };
+        run.hotspots.apply(&mut hotspots);
+        assert!(!hotspots.enabled);
+        assert!(hotspots.cyclomatic);
+        assert_eq!(Some(true), run.git.mailmap);
+        assert_eq!(None, run.git.exclude_bots);
     }
 }
@@ -0,0 +1,66 @@
+use std::fs::File;
+use std::rc::Rc;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use csv::ReaderBuilder;
+use ndarray::Array2;
+
+use ccan::changes::Changes;
+use ccan::matrix::NamedMatrix;
+
+pub fn read_changes(path: &str) -> Result<Changes> {
+    let freqs = read_named_matrix(path)?;
+    Ok(Changes::from_freqs(freqs))
+}
+
+fn read_named_matrix(path: &str) -> Result<NamedMatrix<Rc<String>, DateTime<Utc>>> {
+    let file = File::open(path)?;
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(file);
+    let mut records = reader.records();
+    let header = match records.next() {
+        Some(header) => header?,
+        None => bail!("Empty change history {}", path),
+    };
+    let columns = header.iter()
+        .skip(1)
+        .map(parse_date)
+        .collect::<Result<Vec<DateTime<Utc>>>>()?;
+    let mut rows = Vec::new();
+    let mut values = Vec::new();
+    for record in records {
+        let record = record?;
+        rows.push(Rc::new(record[0].to_string()));
+        for value in record.iter().skip(1) {
+            values.push(value.parse::<f64>()?);
+        }
+    }
+    let mut matrix = NamedMatrix::new(rows, columns, Some("files"), Some("dates"));
+    matrix.matrix = Array2::from_shape_vec(matrix.matrix.raw_dim(), values)?;
+    Ok(matrix)
+}
+
+fn parse_date(s: &str) -> Result<DateTime<Utc>> {
+    let naive = s.strip_suffix(" UTC").unwrap_or(s);
+    match NaiveDateTime::parse_from_str(naive, "%Y-%m-%d %H:%M:%S") {
+        Ok(d) => Ok(d.and_utc()),
+        Err(_) => s.parse::<DateTime<Utc>>().map_err(|e| anyhow!("Cannot parse date {}: {}", s, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::input::parse_date;
+
+    #[test]
+    fn test_parse_written_dates() {
+        let date = Utc.with_ymd_and_hms(2020, 9, 15, 8, 26, 40).unwrap();
+        assert_eq!(date, parse_date(&date.to_string()).unwrap());
+        assert_eq!(date, parse_date("2020-09-15T08:26:40Z").unwrap());
+    }
+}
@@ -12,41 +12,142 @@ extern crate serde;
 extern crate simple_logger;
 
 use anyhow::{bail, Result};
-use args::Args;
+use args::{load_options, AnalyzeArgs, Cli, Command, EvaluateArgs, ExportArgs, MineArgs, PredictArgs, RunArgs};
 use clap::Parser;
 use log::{error, info, warn};
 use simple_logger::SimpleLogger;
 
-use ccan::Analysis;
-use output::{mkdir, write_arr, write_matrix, write_named_matrix};
+use ccan::changes::Changes;
+use ccan::cochanges::CoChanges;
+use ccan::evaluate::{Evaluation, EvaluationOpt};
+use ccan::graph::{Graph, GraphFormat};
+use ccan::predict::{PredictionOpt, RippleChangeProbabilities};
+use ccan::{mine_changes, tag_ranges, Analysis, AnalysisOutput, Options};
+use input::read_changes;
+use output::{mkdir, write_arr, write_communities, write_community_members, write_coordination_gaps, write_coordination_links, write_crossings, write_graph, write_hidden_dependencies, write_hotspots, write_matrix, write_owners, write_named_matrix, write_report, write_text, write_violations};
 
-use crate::output::{csv_file_name, output_dir};
+use crate::args::GraphArgs;
+use crate::output::{create_path, csv_file_name, file_name, output_dir};
 
 mod args;
+mod input;
 mod output;
 
-fn run(args: Args) -> Result<()> {
-    let output_dir = output_dir(&args);
-    let cc_freqs_file = &csv_file_name(&args, "cc_freqs");
-    let cc_probs_file = &csv_file_name(&args, "cc_probs");
-    let cc_files_file = &csv_file_name(&args, "cc_files");
-    let c_data_file = &csv_file_name(&args, "c_hist");
-    let c_ripple_file = &csv_file_name(&args, "c_ripple");
+fn run(args: RunArgs, config: Option<&str>) -> Result<()> {
+    let opts = args.to_options(config)?;
+    if !args.per_tag_pair {
+        return run_analysis(&args, opts, &output_dir(&args));
+    }
+    let ranges = tag_ranges(&args.git.repository, args.tag_pattern.as_deref())?;
+    if ranges.is_empty() {
+        bail!("Fewer than two tags to analyse in {}", args.git.repository)
+    }
+    for range in ranges {
+        let mut range_opts = opts.clone();
+        range_opts.git_opts.commit_filters.ranges = vec![range.clone()];
+        let range_dir = create_path(&[&output_dir(&args), &range.replace('/', "_")]);
+        info!("Analysing range {}", range);
+        run_analysis(&args, range_opts, &range_dir)?;
+    }
+    Ok(())
+}
 
-    info!("Started analysing {}", args.repository.as_str());
-    let skip_predict = args.skip_predict;
-    let mut analysis = Analysis::new(args.to_options());
+fn run_analysis(args: &RunArgs, opts: Options, output_dir: &String) -> Result<()> {
+    let cc_freqs_file = &csv_file_name(output_dir, &opts, "cc_freqs");
+    let cc_probs_file = &csv_file_name(output_dir, &opts, "cc_probs");
+    let cc_files_file = &csv_file_name(output_dir, &opts, "cc_files");
+    let c_data_file = &csv_file_name(output_dir, &opts, "c_hist");
+    let c_ripple_file = &csv_file_name(output_dir, &opts, "c_ripple");
+    let hotspots_file = &csv_file_name(output_dir, &opts, "hotspots");
+    let owners_file = &csv_file_name(output_dir, &opts, "owners_files");
+    let dir_owners_file = &csv_file_name(output_dir, &opts, "owners_dirs");
+    let crossings_file = &csv_file_name(output_dir, &opts, "owners_crossings");
+    let links_file = &csv_file_name(output_dir, &opts, "coordination_links");
+    let gaps_file = &csv_file_name(output_dir, &opts, "coordination_gaps");
+    let communities_file = &csv_file_name(output_dir, &opts, "communities");
+    let members_file = &csv_file_name(output_dir, &opts, "communities_members");
+    let violations_file = &csv_file_name(output_dir, &opts, "architecture_violations");
+    let hidden_file = &csv_file_name(output_dir, &opts, "hidden_dependencies");
+    let model_file = file_name(output_dir, &opts, "cc_model", "ccm");
+    let graph_files = args.graph.graph_format.iter()
+        .map(|f| (*f, file_name(output_dir, &opts, "cc_graph", f.extension())))
+        .collect::<Vec<_>>();
+    let coordination_formats = if args.graph.graph_format.is_empty() {
+        vec![GraphFormat::GraphML]
+    } else {
+        args.graph.graph_format.clone()
+    };
+    let coordination_graph_files = coordination_formats.iter()
+        .map(|f| (*f, file_name(output_dir, &opts, "coordination_graph", f.extension())))
+        .collect::<Vec<_>>();
+    let markdown_file = if args.prediction.markdown {
+        Some(file_name(output_dir, &opts, "c_ripple", "md"))
+    } else {
+        None
+    };
+    let markdown_opts = args.prediction.markdown_opts();
+    let report_file = if args.report {
+        Some(file_name(output_dir, &opts, "report", "html"))
+    } else {
+        None
+    };
+
+    info!("Started analysing {}", args.git.repository.as_str());
+    let skip_predict = opts.pred_opts.skip;
+    let mut analysis = Analysis::new(opts.clone());
     match analysis.run() {
         Ok(output) => {
             info!("Writing output to {}", output_dir.as_str());
-            mkdir(&output_dir)?;
-            write_matrix(cc_freqs_file, &output.co_changes.freqs.matrix)?;
-            write_arr(cc_files_file, &output.co_changes.freqs.col_names)?;
-            write_matrix(cc_probs_file, &output.co_changes.probs.matrix)?;
+            mkdir(output_dir)?;
+            write_co_changes(cc_freqs_file, cc_probs_file, cc_files_file, &output.co_changes)?;
+            output.co_changes.save_file(&model_file)?;
             write_named_matrix(c_data_file, &output.changes.freqs)?;
+            if !graph_files.is_empty() {
+                write_graphs(&graph_files, &output.co_changes, &output.changes, &args.graph)?;
+            }
+            if let Some(hotspots) = &output.hotspots {
+                write_hotspots(hotspots_file, hotspots)?;
+                println!("{}", hotspots);
+            }
+            if let Some(ownership) = &output.ownership {
+                write_owners(owners_file, &ownership.files)?;
+                write_owners(dir_owners_file, &ownership.directories)?;
+                write_crossings(crossings_file, &ownership.crossings)?;
+                println!("{}", ownership);
+            }
+            if let Some(coordination) = &output.coordination {
+                write_coordination_links(links_file, &coordination.links)?;
+                write_coordination_gaps(gaps_file, &coordination.gaps)?;
+                let graph = coordination.to_graph();
+                for (format, graph_file) in coordination_graph_files.iter() {
+                    write_graph(graph_file, &graph, *format)?;
+                }
+                println!("{}", coordination);
+            }
+            if let Some(communities) = &output.communities {
+                write_communities(communities_file, communities)?;
+                write_community_members(members_file, communities)?;
+                println!("{}", communities);
+            }
+            if let Some(violations) = &output.violations {
+                write_violations(violations_file, &violations.violations)?;
+                println!("{}", violations);
+            }
+            if let Some(hidden) = &output.hidden_dependencies {
+                write_hidden_dependencies(hidden_file, &hidden.hidden)?;
+                println!("{}", hidden);
+            }
+            if let Some(report_file) = &report_file {
+                write_report(report_file, output, Some(&opts))?;
+            }
             if !skip_predict {
                 write_arr(c_ripple_file, &output.ripples.get_probabilities())?;
                 println!("{}", &output.ripples);
+                if let Some(markdown_file) = &markdown_file {
+                    let ripples = &output.ripples;
+                    let md = ripples.to_markdown(&output.co_changes, &ripples.changing_files, &markdown_opts);
+                    write_text(markdown_file, &md)?;
+                }
             }
             info!("Completed in {}ms", (&analysis.duration).num_milliseconds());
             Ok(())
@@ -58,13 +159,155 @@ fn run(args: Args) -> Result<()> {
     }
 }
 
+fn mine(args: MineArgs, config: Option<&str>) -> Result<()> {
+    let git_opts = args.to_options(config)?;
+    info!("Started mining {}", args.git.repository.as_str());
+    let changes = mine_changes(&args.git.repository, &git_opts)?;
+    info!(
+        "Mined {} files in {} transactions",
+        changes.freqs.row_names.len(),
+        changes.freqs.col_names.len()
+    );
+    mkdir(&args.output_dir)?;
+    write_named_matrix(&create_path(&[&args.output_dir, "changes.csv"]), &changes.freqs)
+}
+
+fn analyze(args: AnalyzeArgs, config: Option<&str>) -> Result<()> {
+    let opts = load_options(config, None)?;
+    let mut cc_opts = opts.cc_opts;
+    args.cc.apply(&mut cc_opts);
+    let changes = read_changes(&args.changes)?;
+    let components = args.components.as_ref().unwrap_or(&opts.components);
+    let (changes, components) = match components.mapper(args.repository.as_deref(), &opts.git_opts.commit_filters.head())? {
+        Some(mapper) => (mapper.aggregate(&changes), mapper.components(&changes)),
+        None => (changes, Default::default()),
+    };
+    info!("Started analysing {}", args.changes.as_str());
+    let mut co_changes = CoChanges::from_changes(&changes, &cc_opts);
+    co_changes.components = components;
+    mkdir(&args.output_dir)?;
+    write_co_changes(
+        &create_path(&[&args.output_dir, "cc_freqs.csv"]),
+        &create_path(&[&args.output_dir, "cc_probs.csv"]),
+        &create_path(&[&args.output_dir, "cc_files.csv"]),
+        &co_changes,
+    )?;
+    let model_file = create_path(&[&args.output_dir, "model.ccm"]);
+    info!("Writing model to {}", model_file.as_str());
+    co_changes.save_file(&model_file)
+}
+
+fn predict(args: PredictArgs, config: Option<&str>) -> Result<()> {
+    let co_changes = CoChanges::load_file(&args.model, args.algorithm)?;
+    let mut pred_opts = load_options(config, None)?.pred_opts;
+    args.prediction.apply(&mut pred_opts);
+    pred_opts.algorithm = co_changes.opts.algorithm;
+    let ripples = match &args.changes {
+        // models analysed by component predict the components of the changed files
+        Some(changes) => {
+            let changes = co_changes.map_changes(read_changes(changes)?);
+            RippleChangeProbabilities::from(&co_changes, &changes, &pred_opts)
+        }
+        None if !pred_opts.changed_files.is_empty() => {
+            let changed_files = co_changes.map_files(&pred_opts.changed_files);
+            RippleChangeProbabilities::from_files(&co_changes, changed_files, &pred_opts)
+        }
+        None => bail!("Either --changes or --changed-files is required to predict"),
+    };
+    println!("{}", &ripples);
+    mkdir(&args.output_dir)?;
+    write_arr(&create_path(&[&args.output_dir, "c_ripple.csv"]), &ripples.get_probabilities())?;
+    if args.prediction.markdown {
+        let md = ripples.to_markdown(&co_changes, &ripples.changing_files, &args.prediction.markdown_opts());
+        write_text(&create_path(&[&args.output_dir, "c_ripple.md"]), &md)?;
+    }
+    Ok(())
+}
+
+fn evaluate(args: EvaluateArgs, config: Option<&str>) -> Result<()> {
+    let mut cc_opts = load_options(config, None)?.cc_opts;
+    args.cc.apply(&mut cc_opts);
+    let changes = read_changes(&args.changes)?;
+    let mut dates = changes.freqs.col_names.clone();
+    dates.sort();
+    let eval_opts = match EvaluationOpt::default_split(&dates) {
+        Some(split) => args.to_options(split),
+        None => bail!("No transactions to evaluate in {}", args.changes),
+    };
+    let pred_opts = PredictionOpt {
+        skip: false,
+        since_changes: eval_opts.split,
+        until_changes: dates[dates.len() - 1],
+        algorithm: cc_opts.algorithm,
+        changed_files: Vec::new(),
+    };
+    info!("Backtesting on transactions since {}", eval_opts.split);
+    let evaluation = Evaluation::backtest(&changes, &cc_opts, &pred_opts, &eval_opts);
+    println!("{}", &evaluation);
+    mkdir(&args.output_dir)?;
+    write_text(&create_path(&[&args.output_dir, "evaluation.txt"]), &evaluation.to_string())
+}
+
+fn export(args: ExportArgs) -> Result<()> {
+    let changes = read_changes(&args.changes)?;
+    let co_changes = CoChanges::load_file(&args.model, None)?;
+    mkdir(&args.output_dir)?;
+    let graph_files = args.graph.graph_format.iter()
+        .map(|f| (*f, create_path(&[&args.output_dir, &format!("cc_graph.{}", f.extension())])))
+        .collect::<Vec<_>>();
+    write_graphs(&graph_files, &co_changes, &changes, &args.graph)?;
+    if args.report {
+        let output = AnalysisOutput {
+            changes,
+            co_changes,
+            ripples: RippleChangeProbabilities::new(),
+            hotspots: None,
+            ownership: None,
+            coordination: None,
+            communities: None,
+            violations: None,
+            hidden_dependencies: None,
+        };
+        write_report(&create_path(&[&args.output_dir, "report.html"]), &output, None)?;
+    }
+    Ok(())
+}
+
+fn write_co_changes(freqs_file: &String, probs_file: &String, files_file: &String, co_changes: &CoChanges) -> Result<()> {
+    write_matrix(freqs_file, &co_changes.freqs.matrix)?;
+    write_arr(files_file, &co_changes.freqs.col_names)?;
+    write_matrix(probs_file, &co_changes.probs.matrix)
+}
+
+fn write_graphs(
+    graph_files: &[(GraphFormat, String)],
+    co_changes: &CoChanges,
+    changes: &Changes,
+    args: &GraphArgs,
+) -> Result<()> {
+    let graph = Graph::from_cochanges(co_changes, changes, &args.to_options());
+    for (format, graph_file) in graph_files.iter() {
+        write_graph(graph_file, &graph, *format)?;
+    }
+    Ok(())
+}
+
 fn main() {
-    let args = Args::parse();
+    let cli = Cli::parse();
     SimpleLogger::new()
-        .with_level(args.log_level)
+        .with_level(cli.log_level)
         .init()
         .unwrap();
-    match run(args) {
+    let config = cli.config.as_deref();
+    let result = match cli.command {
+        Command::Run(args) => run(args, config),
+        Command::Mine(args) => mine(args, config),
+        Command::Analyze(args) => analyze(args, config),
+        Command::Predict(args) => predict(args, config),
+        Command::Evaluate(args) => evaluate(args, config),
+        Command::Export(args) => export(args),
+    };
+    match result {
         Err(e) => {
             error!("Error occurred: {}", e);
         }
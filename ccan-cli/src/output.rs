@@ -7,30 +7,43 @@ use anyhow::{bail, Result};
 use chrono::{DateTime, Utc};
 use csv::WriterBuilder;
 use itertools::Itertools;
+use log::warn;
 use ndarray::Array2;
 use ndarray_csv::Array2Writer;
 use serde::Serialize;
 
+use ccan::graph::{Graph, GraphFormat};
+use ccan::hotspots::Hotspots;
 use ccan::matrix::NamedMatrix;
+use ccan::architecture::Violation;
+use ccan::communities::Communities;
+use ccan::coordination::{CoordinationGap, CoordinationLink};
+use ccan::dependencies::HiddenDependency;
+use ccan::ownership::{CrossingCoupling, Owners};
+use ccan::report::{write_html_report, ReportOpt};
+use ccan::{AnalysisOutput, Options};
 
-use crate::args::Args;
+use crate::args::RunArgs;
 
-pub fn output_dir(args: &Args) -> String {
-    let basename = Path::new(args.repository.as_str())
+pub fn output_dir(args: &RunArgs) -> String {
+    let basename = Path::new(args.git.repository.as_str())
         .file_name()
         .map_or_else(|| "repo", |p| p.to_str().unwrap());
     create_path(&[args.output_dir.as_str(), "ccan-output", basename])
 }
 
-pub fn csv_file_name(args: &Args, prefix: &str) -> String {
-    let output_dir = output_dir(args);
-    let a = &args.algorithm;
-    let d = &args.date_binning;
-    let c = args.changes_min;
-    let f = args.freq_min;
+pub fn csv_file_name(output_dir: &str, opts: &Options, prefix: &str) -> String {
+    file_name(output_dir, opts, prefix, "csv")
+}
+
+pub fn file_name(output_dir: &str, opts: &Options, prefix: &str, extension: &str) -> String {
+    let a = &opts.cc_opts.algorithm;
+    let d = &opts.git_opts.commit_filters.binning;
+    let c = opts.cc_opts.changes_min;
+    let f = opts.cc_opts.freq_min;
     create_path(&[
-        output_dir.as_str(),
-        format!("{prefix}-a{a}-d{d}-c{c}-f{f}.csv").as_str(),
+        output_dir,
+        format!("{prefix}-a{a}-d{d}-c{c}-f{f}.{extension}").as_str(),
     ])
 }
 
@@ -97,6 +110,170 @@ pub fn write_named_matrix(
     Ok(())
 }
 
+pub fn write_graph(path: &String, graph: &Graph, format: GraphFormat) -> Result<()> {
+    if graph.nodes.is_empty() {
+        warn!("No edges above the minimum weight, writing an empty graph to {}", path);
+    }
+    let mut file = File::create(path)?;
+    graph.write(&mut file, format)
+}
+
+pub fn write_report(path: &String, output: &AnalysisOutput, opts: Option<&Options>) -> Result<()> {
+    let title = match opts {
+        Some(opts) => {
+            let basename = Path::new(opts.repository.as_str())
+                .file_name()
+                .map_or_else(|| "repo", |p| p.to_str().unwrap());
+            format!("Co-change report for {basename}")
+        }
+        None => ReportOpt::default().title,
+    };
+    let report_opt = ReportOpt {
+        title,
+        ..ReportOpt::default()
+    };
+    let mut file = File::create(path)?;
+    write_html_report(&mut file, output, opts, &report_opt)
+}
+
+pub fn write_hotspots(path: &String, hotspots: &Hotspots) -> Result<()> {
+    let file = File::create(path)?;
+    let mut writer = WriterBuilder::new().from_writer(file);
+    let mut header = ["file", "score", "changes", "churn", "loc", "indentation", "mean_indentation", "max_indentation", "cyclomatic", "slope"]
+        .iter()
+        .map(|h| h.to_string())
+        .collect::<Vec<String>>();
+    header.extend(hotspots.periods.iter().map(|p| p.format("%Y-%m-%d").to_string()));
+    writer.write_record(header)?;
+    for h in hotspots.hotspots.iter() {
+        let mut record = vec![
+            h.file.clone(),
+            h.score.to_string(),
+            h.changes.to_string(),
+            h.churn.to_string(),
+            h.metrics.loc.to_string(),
+            h.metrics.indentation.to_string(),
+            h.metrics.mean_indentation.to_string(),
+            h.metrics.max_indentation.to_string(),
+            h.metrics.cyclomatic.map(|c| c.to_string()).unwrap_or_default(),
+            h.slope.to_string(),
+        ];
+        record.extend(h.trend.iter().map(|t| t.to_string()));
+        writer.write_record(record)?;
+    }
+    Ok(writer.flush()?)
+}
+
+pub fn write_owners(path: &String, owners: &[Owners]) -> Result<()> {
+    let file = File::create(path)?;
+    let mut writer = WriterBuilder::new().from_writer(file);
+    writer.write_record(["name", "owner", "owner_share", "bus_factor", "knowledge_loss", "authors", "shares"])?;
+    for o in owners {
+        let (owner, share) = o.owner().cloned().unwrap_or_default();
+        writer.write_record([
+            o.name.clone(),
+            owner,
+            share.to_string(),
+            o.bus_factor.to_string(),
+            o.knowledge_loss.to_string(),
+            o.shares.len().to_string(),
+            o.shares.iter().map(|(a, s)| format!("{}={:.3}", a, s)).join(";"),
+        ])?;
+    }
+    Ok(writer.flush()?)
+}
+
+pub fn write_crossings(path: &String, crossings: &[CrossingCoupling]) -> Result<()> {
+    let file = File::create(path)?;
+    let mut writer = WriterBuilder::new().from_writer(file);
+    writer.write_record(["file", "owner", "other_file", "other_owner", "probability"])?;
+    for c in crossings {
+        writer.write_record([&c.files.0, &c.owners.0, &c.files.1, &c.owners.1, &c.probability.to_string()])?;
+    }
+    Ok(writer.flush()?)
+}
+
+pub fn write_coordination_links(path: &String, links: &[CoordinationLink]) -> Result<()> {
+    let file = File::create(path)?;
+    let mut writer = WriterBuilder::new().from_writer(file);
+    writer.write_record(["developer", "other_developer", "weight", "pairs"])?;
+    for l in links {
+        writer.write_record([&l.developers.0, &l.developers.1, &l.weight.to_string(), &l.pairs.to_string()])?;
+    }
+    Ok(writer.flush()?)
+}
+
+pub fn write_coordination_gaps(path: &String, gaps: &[CoordinationGap]) -> Result<()> {
+    let file = File::create(path)?;
+    let mut writer = WriterBuilder::new().from_writer(file);
+    writer.write_record(["file", "authors", "other_file", "other_authors", "probability"])?;
+    for g in gaps {
+        writer.write_record([&g.files.0, &g.authors.0.join(";"), &g.files.1, &g.authors.1.join(";"), &g.probability.to_string()])?;
+    }
+    Ok(writer.flush()?)
+}
+
+pub fn write_communities(path: &String, communities: &Communities) -> Result<()> {
+    let file = File::create(path)?;
+    let mut writer = WriterBuilder::new().from_writer(file);
+    writer.write_record(["community", "files", "internal_weight", "cut_weight", "conductance", "directory", "purity"])?;
+    for c in communities.communities.iter() {
+        writer.write_record([
+            c.id.to_string(),
+            c.files.len().to_string(),
+            c.internal_weight.to_string(),
+            c.cut_weight.to_string(),
+            c.conductance.to_string(),
+            c.directory.clone(),
+            c.purity.to_string(),
+        ])?;
+    }
+    Ok(writer.flush()?)
+}
+
+pub fn write_community_members(path: &String, communities: &Communities) -> Result<()> {
+    let file = File::create(path)?;
+    let mut writer = WriterBuilder::new().from_writer(file);
+    writer.write_record(["file", "community", "community_directory"])?;
+    for (file, c) in communities.membership.iter() {
+        writer.write_record([file, &c.to_string(), &communities.communities[*c].directory])?;
+    }
+    Ok(writer.flush()?)
+}
+
+pub fn write_violations(path: &String, violations: &[Violation]) -> Result<()> {
+    let file = File::create(path)?;
+    let mut writer = WriterBuilder::new().from_writer(file);
+    writer.write_record(["file", "layer", "other_file", "other_layer", "probability", "score", "last_change", "commits"])?;
+    for v in violations {
+        writer.write_record([
+            v.files.0.clone(),
+            v.layers.0.clone(),
+            v.files.1.clone(),
+            v.layers.1.clone(),
+            v.probability.to_string(),
+            v.score.to_string(),
+            v.last_change.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default(),
+            v.commits.iter().map(|c| c.sha1.as_str()).join(";"),
+        ])?;
+    }
+    Ok(writer.flush()?)
+}
+
+pub fn write_hidden_dependencies(path: &String, hidden: &[HiddenDependency]) -> Result<()> {
+    let file = File::create(path)?;
+    let mut writer = WriterBuilder::new().from_writer(file);
+    writer.write_record(["file", "other_file", "probability", "file_scanned", "other_file_scanned"])?;
+    for d in hidden {
+        writer.write_record([&d.files.0, &d.files.1, &d.probability.to_string(), &d.scanned.0.to_string(), &d.scanned.1.to_string()])?;
+    }
+    Ok(writer.flush()?)
+}
+
+pub fn write_text(path: &String, text: &str) -> Result<()> {
+    Ok(fs::write(path, text)?)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::output::create_path;
@@ -0,0 +1,309 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::rc::Rc;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+use regex::Regex;
+use serde::Deserialize;
+use toml;
+
+use bettergit::{glob_to_regex, BetterCommit, GroupedBetterDiffs};
+use cochanges::CoChanges;
+
+const DISPLAYED_VIOLATIONS: usize = 10;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ArchitectureOpt {
+    /// TOML file declaring the layers and the couplings allowed between them.
+    pub file: Option<String>,
+    /// Co-change probability from which a coupling between layers is reported.
+    pub min_probability: f64,
+    /// Days after which the recency of a violation halves its score.
+    pub half_life_days: i64,
+}
+
+impl Default for ArchitectureOpt {
+    fn default() -> Self {
+        ArchitectureOpt {
+            file: None,
+            min_probability: 0.2,
+            half_life_days: 90,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LayerSpec {
+    name: String,
+    globs: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ArchitectureSpec {
+    layers: Vec<LayerSpec>,
+    #[serde(default)]
+    allowed: Vec<(String, String)>,
+}
+
+/// The intended layers of a repository, each matching files by glob, the first matching layer wins.
+/// Files of the same layer may always change together, files of two layers only if the pair is allowed,
+/// in either order.
+pub struct Architecture {
+    pub layers: Vec<(String, Vec<Regex>)>,
+    pub allowed: Vec<(String, String)>,
+}
+
+impl Architecture {
+    pub fn from_file(path: &str) -> Result<Architecture> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("cannot read architecture {}: {}", path, e))?;
+        Architecture::parse(&content).map_err(|e| anyhow!("invalid architecture {}: {}", path, e))
+    }
+
+    pub fn parse(content: &str) -> Result<Architecture> {
+        let spec: ArchitectureSpec = toml::from_str(content)?;
+        let mut layers = Vec::new();
+        for layer in spec.layers {
+            let globs = layer.globs.iter()
+                .map(|g| Regex::new(&glob_to_regex(g)).map_err(|e| anyhow!("invalid glob {}: {}", g, e)))
+                .collect::<Result<Vec<Regex>>>()?;
+            layers.push((layer.name, globs));
+        }
+        for (a, b) in spec.allowed.iter() {
+            for name in [a, b] {
+                if !layers.iter().any(|(l, _)| l == name) {
+                    bail!("unknown layer {} in allowed couplings", name);
+                }
+            }
+        }
+        Ok(Architecture { layers, allowed: spec.allowed })
+    }
+
+    pub fn layer_of(&self, path: &str) -> Option<&str> {
+        self.layers.iter()
+            .find(|(_, globs)| globs.iter().any(|g| g.is_match(path)))
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn allows(&self, a: &str, b: &str) -> bool {
+        a == b || self.allowed.iter().any(|(x, y)| (x == a && y == b) || (x == b && y == a))
+    }
+}
+
+/// The transactions changing each file, and the commits of each transaction that changed it.
+pub struct ChangeHistory {
+    transactions: HashMap<String, BTreeMap<DateTime<Utc>, Vec<Rc<BetterCommit>>>>,
+}
+
+impl ChangeHistory {
+    /// `name_of` gives the row of the co-changes a changed file counts for, e.g. its component.
+    pub fn from_diffs<F>(diffs: &GroupedBetterDiffs, name_of: F) -> ChangeHistory
+    where F: Fn(&str) -> Option<String>
+    {
+        let mut transactions = HashMap::<String, BTreeMap<DateTime<Utc>, Vec<Rc<BetterCommit>>>>::new();
+        for (date, diff) in diffs.iter() {
+            for c in diff.contributions.iter() {
+                if let Some(name) = name_of(&c.file) {
+                    let commits = transactions.entry(name).or_default().entry(*date).or_default();
+                    if !commits.iter().any(|x| x.sha1 == c.commit.sha1) {
+                        commits.push(c.commit.clone());
+                    }
+                }
+            }
+        }
+        ChangeHistory { transactions }
+    }
+
+    /// Transactions changing both files, oldest first, with the commits changing either of them.
+    pub fn shared(&self, a: &str, b: &str) -> Vec<(DateTime<Utc>, Vec<Rc<BetterCommit>>)> {
+        let (a, b) = match (self.transactions.get(a), self.transactions.get(b)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Vec::new(),
+        };
+        a.iter()
+            .filter_map(|(date, commits)| {
+                let others = b.get(date)?;
+                let commits = commits.iter().chain(others.iter())
+                    .unique_by(|c| c.sha1.clone())
+                    .sorted_by_key(|c| c.when)
+                    .cloned()
+                    .collect();
+                Some((*date, commits))
+            })
+            .collect()
+    }
+
+    fn last_change(&self, file: &str) -> Option<DateTime<Utc>> {
+        self.transactions.get(file).and_then(|t| t.keys().last()).cloned()
+    }
+
+    fn newest(&self) -> Option<DateTime<Utc>> {
+        self.transactions.values().filter_map(|t| t.keys().last()).max().cloned()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Violation {
+    pub files: (String, String),
+    pub layers: (String, String),
+    /// Co-change probability of the files, see `CoChanges::coupled_pairs`.
+    pub probability: f64,
+    /// The last transaction changing both files, if any; models such as the naive one also couple files
+    /// changed at close dates.
+    pub last_change: Option<DateTime<Utc>>,
+    /// Probability halved for every `half_life_days` between the last change and the newest transaction,
+    /// or the older of the files' last changes if they never changed together.
+    pub score: f64,
+    /// The commits of the transactions changing both files, oldest first.
+    pub commits: Vec<Rc<BetterCommit>>,
+}
+
+/// Co-changes between layers whose coupling is not allowed, highest scores first.
+pub struct Violations {
+    pub violations: Vec<Violation>,
+    /// Co-changing files matching no layer.
+    pub unassigned: Vec<String>,
+}
+
+impl Violations {
+    pub fn detect(architecture: &Architecture, cc: &CoChanges, history: &ChangeHistory, opt: &ArchitectureOpt) -> Violations {
+        let probs = &cc.probs;
+        let layers = probs.row_names.iter()
+            .map(|f| architecture.layer_of(f))
+            .collect::<Vec<Option<&str>>>();
+        let newest = history.newest();
+        let mut violations = Vec::new();
+        for (i, j, probability) in cc.coupled_pairs(opt.min_probability) {
+            let (a, b) = match (layers[i], layers[j]) {
+                (Some(a), Some(b)) if !architecture.allows(a, b) => (a, b),
+                _ => continue,
+            };
+            let shared = history.shared(&probs.row_names[i], &probs.row_names[j]);
+            let last_change = shared.last().map(|(date, _)| *date);
+            let last_active = last_change.or_else(|| {
+                history.last_change(&probs.row_names[i]).min(history.last_change(&probs.row_names[j]))
+            });
+            let age = match (newest, last_active) {
+                (Some(newest), Some(last)) => (newest - last).num_days() as f64,
+                _ => 0.0,
+            };
+            let half_life = opt.half_life_days.max(1) as f64;
+            violations.push(Violation {
+                files: (probs.row_names[i].to_string(), probs.row_names[j].to_string()),
+                layers: (a.to_string(), b.to_string()),
+                probability,
+                last_change,
+                score: probability * 0.5f64.powf(age / half_life),
+                commits: shared.into_iter().flat_map(|(_, commits)| commits).collect(),
+            });
+        }
+        violations.sort_by(|x, y| y.score.total_cmp(&x.score).then(x.files.cmp(&y.files)));
+        let unassigned = probs.row_names.iter()
+            .zip(layers.iter())
+            .filter(|(_, layer)| layer.is_none())
+            .map(|(f, _)| f.to_string())
+            .collect();
+        Violations { violations, unassigned }
+    }
+}
+
+impl Display for Violations {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Architecture violations: {}", self.violations.len())?;
+        if !self.unassigned.is_empty() {
+            writeln!(f, "Files in no layer: {}", self.unassigned.len())?;
+        }
+        for v in self.violations.iter().take(DISPLAYED_VIOLATIONS) {
+            let last = v.last_change.map_or("-".to_string(), |d| d.format("%Y-%m-%d").to_string());
+            writeln!(
+                f, "{:>10.2}   {} ({}) <-> {} ({}), p = {:.2}, {} commits, last {}",
+                v.score, v.files.0, v.layers.0, v.files.1, v.layers.1, v.probability, v.commits.len(), last
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use chrono::{TimeZone, Utc};
+
+    use architecture::{Architecture, ArchitectureOpt, ChangeHistory, Violations};
+    use bettergit::diffs_of;
+    use cochanges::{CCMatrix, CoChanges, CoChangesOpt};
+
+    const ARCHITECTURE: &str = r#"
+allowed = [["api", "core"], ["core", "db"]]
+
+[[layers]]
+name = "api"
+globs = ["src/api/**"]
+
+[[layers]]
+name = "core"
+globs = ["src/core/**", "src/model.rs"]
+
+[[layers]]
+name = "db"
+globs = ["src/db/**"]
+"#;
+
+    #[test]
+    fn test_architecture() {
+        let architecture = Architecture::parse(ARCHITECTURE).unwrap();
+        assert_eq!(Some("core"), architecture.layer_of("src/model.rs"));
+        assert_eq!(Some("api"), architecture.layer_of("src/api/v1/routes.rs"));
+        assert_eq!(None, architecture.layer_of("README.md"));
+        assert!(architecture.allows("core", "api"));
+        assert!(architecture.allows("db", "db"));
+        assert!(!architecture.allows("api", "db"));
+        assert!(Architecture::parse("allowed = [[\"api\", \"ui\"]]\n[[layers]]\nname = \"api\"\nglobs = []\n").is_err());
+    }
+
+    #[test]
+    fn test_violations() {
+        let start = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        // days 0 and 200 change the api and the database together
+        let diffs = diffs_of(&[
+            (0, "Alice", "src/api/routes.rs"), (0, "Bob", "src/db/store.rs"),
+            (100, "Alice", "src/api/routes.rs"), (100, "Alice", "src/core/service.rs"),
+            (200, "Alice", "src/api/handler.rs"), (200, "Alice", "src/db/store.rs"),
+        ]);
+        let history = ChangeHistory::from_diffs(&diffs, |f| Some(f.to_string()));
+
+        let names = ["src/api/handler.rs", "src/api/routes.rs", "src/core/service.rs", "src/db/store.rs", "README.md"]
+            .iter()
+            .map(|f| Rc::new(f.to_string()))
+            .collect::<Vec<_>>();
+        let mut probs = CCMatrix::new(names.clone(), names.clone(), Some("impacted"), Some("changing"));
+        probs.matrix[[1, 3]] = 0.6;
+        probs.matrix[[0, 3]] = 0.3;
+        probs.matrix[[3, 0]] = 0.5;
+        probs.matrix[[1, 2]] = 0.9;
+        probs.matrix[[4, 3]] = 0.9;
+        let freqs = CCMatrix::new(names.clone(), names, Some("impacted"), Some("changed"));
+        let cc = CoChanges { freqs, probs, opts: CoChangesOpt::default(), components: Default::default() };
+
+        let opt = ArchitectureOpt { half_life_days: 200, ..ArchitectureOpt::default() };
+        let violations = Violations::detect(&Architecture::parse(ARCHITECTURE).unwrap(), &cc, &history, &opt);
+        assert_eq!(vec!["README.md"], violations.unassigned);
+        let found = violations.violations.iter()
+            .map(|v| (v.files.1.as_str(), v.files.0.as_str(), v.commits.iter().map(|c| c.sha1.as_str()).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        // routes.rs is more likely to change with store.rs, but handler.rs changed with it 200 days later
+        assert_eq!(vec![
+            ("src/db/store.rs", "src/api/handler.rs", vec!["200Alice"]),
+            ("src/db/store.rs", "src/api/routes.rs", vec!["0Alice", "0Bob"]),
+        ], found);
+        assert_eq!(0.5, violations.violations[0].score);
+        assert_eq!(Some(start), violations.violations[1].last_change);
+        assert!((violations.violations[1].score - 0.3).abs() < 1e-9);
+    }
+}
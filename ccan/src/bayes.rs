@@ -12,6 +12,8 @@ use crate::{
     predict::{CRVector, RippleChangePredictor},
 };
 
+/// Transactions changing both files, whatever the weight of the changes, so that
+/// probabilities stay within [0, 1] and `freq_min` counts transactions under any churn weighting.
 fn co_change(v1: ArrayView1<f64>, v2: ArrayView1<f64>) -> f64 {
     v1.iter()
         .zip_eq(v2)
@@ -23,16 +25,14 @@ pub struct BayesianModel;
 impl Model for BayesianModel {}
 impl CCFreqsCalculator for BayesianModel {
     fn calculate_freqs(&self, changes: &Changes, opts: &CoChangesOpt) -> CCMatrix {
+        let c_freq = &changes.c_freq;
         let changes = &changes.freqs;
-        let min_change_freq = opts.changes_min as f64;
-        let mut filt_row_names = Vec::<Rc<String>>::new();
-        for row in changes.row_names.iter() {
-            if let Some(i) = changes.index_of_row(row) {
-                if changes.matrix.row(i).sum() >= min_change_freq {
-                    filt_row_names.push(row.clone());
-                }
-            }
-        }
+        let min_change_freq = opts.changes_min as i32;
+        // the files changed often enough, by their row in `changes`
+        let rows = (0..changes.row_names.len())
+            .filter(|i| c_freq[*i] >= min_change_freq)
+            .collect::<Vec<usize>>();
+        let filt_row_names = rows.iter().map(|i| changes.row_names[*i].clone()).collect::<Vec<Rc<String>>>();
 
         let n = filt_row_names.len();
         let mut cc_freq = CCMatrix::new(
@@ -43,12 +43,12 @@ impl CCFreqsCalculator for BayesianModel {
         );
         debug!("Calculating co-change coefficient");
         for i in 0..n {
-            let row_i = changes.matrix.row(i);
-            for j in 0..n {
+            let row_i = changes.matrix.row(rows[i]);
+            for (j, row) in rows.iter().enumerate() {
                 if i == j {
                     continue;
                 }
-                let row_j = changes.matrix.row(j);
+                let row_j = changes.matrix.row(*row);
                 cc_freq.matrix[[i, j]] = co_change(row_i, row_j);
             }
         }
@@ -66,19 +66,16 @@ impl CCProbsCalculator for BayesianModel {
             Some("changing"),
         );
         let n_vers = changes.n_vers;
-        let priori = freqs.matrix.mapv(|x| x / n_vers); // P(impacted /\ changing)
-        let evidence = &changes.c_prob; // P(changing)
-        for i in 0..cc_probs.matrix.nrows() {
-            let e1 = evidence[i];
-            if e1 < 1e-6 {
+        let joint = freqs.matrix.mapv(|x| x / n_vers); // P(impacted /\ changing)
+        let evidence = freqs.row_names.iter() // P(changing)
+            .map(|f| changes.freqs.index_of_row(f).map_or(0.0, |k| changes.c_prob[k]))
+            .collect::<Vec<f64>>();
+        for j in 0..cc_probs.matrix.ncols() {
+            if evidence[j] < 1e-6 {
                 continue;
             }
-            for j in 0..cc_probs.matrix.ncols() {
-                let e2 = evidence[j];
-                if e2 < 1e-6 {
-                    continue;
-                }
-                cc_probs.matrix[[i, j]] = priori[[i, j]] * e1 / e2 // P(impacted | changing)
+            for i in 0..cc_probs.matrix.nrows() {
+                cc_probs.matrix[[i, j]] = joint[[i, j]] / evidence[j] // P(impacted | changing)
             }
         }
         return cc_probs;
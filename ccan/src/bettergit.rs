@@ -1,80 +1,389 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs;
 use std::fmt::{Display, Formatter};
 use std::ops::Sub;
+use std::path::Path;
 use std::rc::Rc;
 use std::str::FromStr;
 
 use anyhow::{anyhow, bail, Result};
 use chrono::{Datelike, DateTime, Days, TimeZone, Utc};
-use git2::{Commit, Diff, Object, ObjectType, Repository, Sort};
+use git2::{Commit, Delta, Diff, Mailmap, Object, ObjectType, Oid, Patch, Repository, RevparseMode, Revwalk, Sort, TreeWalkMode, TreeWalkResult};
 use itertools::Itertools;
-use log::debug;
+use log::{debug, warn};
 use regex::{Error, Regex, RegexBuilder};
+use serde::Deserialize;
+
+use changes::ChurnWeighting;
+use config::{deserialize_since, deserialize_until};
+use conventional::ConventionalParser;
+use transactions::{TransactionGrouping, TransactionKeys, DEFAULT_ISSUE_REGEX, DEFAULT_SESSION_GAP};
+use gitattributes::{AttributeFiles, AttributesScope, GitAttributes, ATTRIBUTES_FILE_NAME};
+use symbols::{enclosing_symbol, Granularity, SymbolParser};
+
+pub const DEFAULT_EXCLUDE_GLOBS: &[&str] = &[
+    "*.json", "*.lock", "*.sh", "*.proto", "*.bat", "*.md", "*.txt", "*.yaml", "*.yml",
+    "*Dockerfile", "*.mod", "*.sum", ".DS_Store", ".gitignore", ".gitattributes",
+];
+
+pub const IGNORE_FILE_NAME: &str = ".ccanignore";
+
+pub const BOT_AUTHORS: &[&str] = &[
+    r"\[bot\]", r"^dependabot", r"^renovate", r"^greenkeeper", r"^snyk-bot", r"^github-actions",
+    r"^semantic-release-bot", r"^release-bot", r"^pre-commit-ci", r"^mergify", r"^imgbot",
+    r"^allcontributors", r"^codecov", r"^gitlab-bot", r"^copybara", r"noreply@dependabot\.com",
+];
 
 #[derive(Debug, Clone, Hash)]
 pub struct BetterCommit {
     pub sha1: String,
     pub author: String,
+    pub email: String,
     pub when: DateTime<Utc>,
+    pub summary: String,
 }
 
+/// Files changed by the commits of a transaction, each compared to its first parent.
 pub struct BetterDiff {
     pub parent: Rc<BetterCommit>,
     pub child: Rc<BetterCommit>,
+    pub commits: Vec<Rc<BetterCommit>>,
     pub old_files: Vec<Rc<String>>,
     pub new_files: Vec<Rc<String>>,
+    /// Lines inserted in each of the `new_files`.
+    pub insertions: Vec<usize>,
+    /// Lines deleted from each of the `new_files`.
+    pub deletions: Vec<usize>,
+    /// How each of the `new_files` changed.
+    pub kinds: Vec<ChangeKind>,
+    /// The files changed by each commit, before merging them in `new_files`.
+    pub contributions: Vec<Contribution>,
 }
 
-#[derive(Clone)]
+/// A file changed by one commit, and the lines inserted and deleted in it.
+#[derive(Clone, Debug)]
+pub struct Contribution {
+    pub file: Rc<String>,
+    pub commit: Rc<BetterCommit>,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
 pub struct BetterGitOpt {
+    #[serde(flatten)]
     pub commit_filters: CommitFilteringOpt,
-    pub file_filters: FileFilteringOpt
+    #[serde(flatten)]
+    pub file_filters: FileFilteringOpt,
+    pub granularity: Granularity,
+    /// How `Changes.freqs` are weighted by the lines changed in each file.
+    pub churn: ChurnWeighting,
+    /// Changes ignored when counting co-changes, e.g. files being added or deleted.
+    pub exclude_changes: Vec<ChangeKind>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Deserialize)]
+#[serde(default)]
 pub struct CommitFilteringOpt {
     pub branch: String,
+    /// Revisions or globs such as `refs/heads/release/*` mined in addition to `branch`.
+    pub refs: Vec<String>,
+    pub all_refs: bool,
+    /// Revision ranges such as `v2.0..v3.0`, `v2.0...feature` or `^v1.0`, mined instead of `branch`.
+    pub ranges: Vec<String>,
+    #[serde(deserialize_with = "deserialize_until")]
     pub until: DateTime<Utc>,
+    #[serde(deserialize_with = "deserialize_since")]
     pub since: DateTime<Utc>,
     pub binning: DateGrouping,
+    pub transactions: TransactionGrouping,
+    pub issue_regex: String,
+    pub session_gap: String,
+    pub include_authors: Vec<String>,
+    pub exclude_authors: Vec<String>,
+    pub exclude_bots: bool,
+    pub mailmap: bool,
+    pub message_regex: Option<String>,
+    pub exclude_message_regex: Option<String>,
+    pub include_types: Vec<String>,
+    pub exclude_types: Vec<String>,
+    pub include_scopes: Vec<String>,
+    pub exclude_scopes: Vec<String>,
 }
 
-#[derive(Clone)]
+struct CommitFilter {
+    include_authors: Option<Regex>,
+    exclude_authors: Option<Regex>,
+    include_messages: Option<Regex>,
+    exclude_messages: Option<Regex>,
+    include_types: Vec<String>,
+    exclude_types: Vec<String>,
+    include_scopes: Vec<String>,
+    exclude_scopes: Vec<String>,
+    conventional: ConventionalParser,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "FileFilteringSpec")]
 pub struct FileFilteringOpt {
     pub exclude_paths: Regex,
-    pub include_paths: Regex
+    pub include_paths: Regex,
+    pub attributes: AttributesScope,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct FileFilteringSpec {
+    include: Vec<String>,
+    include_globs: Vec<String>,
+    exclude: Option<Vec<String>>,
+    exclude_globs: Option<Vec<String>>,
+    attributes: AttributesScope,
 }
 
 pub type GroupedBetterDiffs = HashMap<DateTime<Utc>, BetterDiff>;
 
 impl BetterCommit {
-    fn from(commit: &Commit) -> BetterCommit {
+    fn from(commit: &Commit, mailmap: Option<&Mailmap>) -> BetterCommit {
+        let author = match mailmap.map(|m| commit.author_with_mailmap(m)) {
+            Some(Ok(author)) => author,
+            _ => commit.author(),
+        };
         BetterCommit {
             sha1: commit.id().to_string(),
-            author: commit.author().name().unwrap_or("<no-author-name>").to_string(),
+            author: author.name().unwrap_or("<no-author-name>").to_string(),
+            email: author.email().unwrap_or("").to_string(),
             when: Utc.timestamp_opt(commit.time().seconds(), 0).unwrap(),
+            summary: commit.summary().unwrap_or("").to_string(),
         }
     }
 }
 impl BetterDiff {
-    fn new(parent: Rc<BetterCommit>, child: Rc<BetterCommit>) -> BetterDiff {
+    pub(crate) fn new(parent: Rc<BetterCommit>, child: Rc<BetterCommit>) -> BetterDiff {
         BetterDiff {
             parent,
             child,
+            commits: Vec::new(),
             old_files: Vec::new(),
             new_files: Vec::new(),
+            insertions: Vec::new(),
+            deletions: Vec::new(),
+            kinds: Vec::new(),
+            contributions: Vec::new(),
+        }
+    }
+
+    /// Lines inserted and deleted in the i-th of the `new_files`.
+    pub fn churn(&self, i: usize) -> usize {
+        self.insertions.get(i).unwrap_or(&0) + self.deletions.get(i).unwrap_or(&0)
+    }
+
+    /// Merges the files changed more than once in the transaction, adding up their churn.
+    fn dedup_files(&mut self) {
+        let mut index = HashMap::<Rc<String>, usize>::new();
+        let mut merged = BetterDiff::new(self.parent.clone(), self.child.clone());
+        for (i, new_file) in self.new_files.iter().enumerate() {
+            let (insertions, deletions) = (self.insertions.get(i).copied().unwrap_or(0), self.deletions.get(i).copied().unwrap_or(0));
+            let kind = self.kinds.get(i).copied().unwrap_or(ChangeKind::Modified);
+            match index.get(new_file) {
+                Some(j) => {
+                    merged.insertions[*j] += insertions;
+                    merged.deletions[*j] += deletions;
+                    merged.kinds[*j] = merged.kinds[*j].then(kind);
+                }
+                None => {
+                    index.insert(new_file.clone(), merged.new_files.len());
+                    merged.old_files.push(self.old_files[i].clone());
+                    merged.new_files.push(new_file.clone());
+                    merged.insertions.push(insertions);
+                    merged.deletions.push(deletions);
+                    merged.kinds.push(kind);
+                }
+            }
+        }
+        self.old_files = merged.old_files;
+        self.new_files = merged.new_files;
+        self.insertions = merged.insertions;
+        self.deletions = merged.deletions;
+        self.kinds = merged.kinds;
+    }
+}
+
+impl Default for CommitFilteringOpt {
+    fn default() -> Self {
+        CommitFilteringOpt {
+            branch: "HEAD".to_string(),
+            refs: Vec::new(),
+            all_refs: false,
+            ranges: Vec::new(),
+            until: Utc.with_ymd_and_hms(9999, 1, 1, 23, 59, 59).unwrap(),
+            since: Utc.with_ymd_and_hms(1900, 1, 1, 0, 0, 0).unwrap(),
+            binning: DateGrouping::None,
+            transactions: TransactionGrouping::Dates,
+            issue_regex: DEFAULT_ISSUE_REGEX.to_string(),
+            session_gap: DEFAULT_SESSION_GAP.to_string(),
+            include_authors: Vec::new(),
+            exclude_authors: Vec::new(),
+            exclude_bots: false,
+            mailmap: false,
+            message_regex: None,
+            exclude_message_regex: None,
+            include_types: Vec::new(),
+            exclude_types: Vec::new(),
+            include_scopes: Vec::new(),
+            exclude_scopes: Vec::new(),
+        }
+    }
+}
+
+impl CommitFilteringOpt {
+    /// The newest mined revision: the end of the last revision range, or the branch.
+    pub fn head(&self) -> String {
+        match self.ranges.iter().rev().find(|r| !r.starts_with('^')) {
+            Some(range) => match range.rsplit("..").next().map(|r| r.trim_start_matches('.')) {
+                Some(to) if !to.is_empty() => to.to_string(),
+                _ => "HEAD".to_string(),
+            },
+            None => self.branch.clone(),
         }
     }
 }
 
+impl CommitFilter {
+    fn new(filters: &CommitFilteringOpt) -> Result<CommitFilter> {
+        let mut exclude = filters.exclude_authors.iter().map(String::as_str).collect::<Vec<_>>();
+        if filters.exclude_bots {
+            exclude.extend(BOT_AUTHORS);
+        }
+        let to_regex = |patterns: &[&str], what: &str| -> Result<Option<Regex>> {
+            if patterns.is_empty() {
+                return Ok(None);
+            }
+            let regex = RegexBuilder::new(&patterns.join("|")).case_insensitive(true).build()
+                .map_err(|e| anyhow!("invalid {} regex: {}", what, e))?;
+            Ok(Some(regex))
+        };
+        let include = filters.include_authors.iter().map(String::as_str).collect::<Vec<_>>();
+        let lowercase = |v: &Vec<String>| v.iter().map(|s| s.to_lowercase()).collect::<Vec<_>>();
+        Ok(CommitFilter {
+            include_authors: to_regex(&include, "author")?,
+            exclude_authors: to_regex(&exclude, "author")?,
+            include_messages: to_regex(&filters.message_regex.as_deref().into_iter().collect::<Vec<_>>(), "message")?,
+            exclude_messages: to_regex(&filters.exclude_message_regex.as_deref().into_iter().collect::<Vec<_>>(), "message")?,
+            include_types: lowercase(&filters.include_types),
+            exclude_types: lowercase(&filters.exclude_types),
+            include_scopes: lowercase(&filters.include_scopes),
+            exclude_scopes: lowercase(&filters.exclude_scopes),
+            conventional: ConventionalParser::new(),
+        })
+    }
+
+    /// Matches the author's `Name <email>` and the full message against the patterns.
+    fn matches(&self, commit: &BetterCommit, message: &str) -> bool {
+        let identity = format!("{} <{}>", commit.author, commit.email);
+        self.include_authors.as_ref().is_none_or(|r| r.is_match(&identity))
+            && !self.exclude_authors.as_ref().is_some_and(|r| r.is_match(&identity))
+            && self.include_messages.as_ref().is_none_or(|r| r.is_match(message))
+            && !self.exclude_messages.as_ref().is_some_and(|r| r.is_match(message))
+            && self.matches_conventional(&commit.summary)
+    }
+
+    fn matches_conventional(&self, summary: &str) -> bool {
+        if self.include_types.is_empty() && self.exclude_types.is_empty()
+            && self.include_scopes.is_empty() && self.exclude_scopes.is_empty() {
+            return true;
+        }
+        let commit = self.conventional.parse(summary);
+        let kind = commit.as_ref().map(|c| &c.kind);
+        let scope = commit.as_ref().and_then(|c| c.scope.as_ref());
+        (self.include_types.is_empty() || kind.is_some_and(|k| self.include_types.contains(k)))
+            && !kind.is_some_and(|k| self.exclude_types.contains(k))
+            && (self.include_scopes.is_empty() || scope.is_some_and(|s| self.include_scopes.contains(s)))
+            && !scope.is_some_and(|s| self.exclude_scopes.contains(s))
+    }
+}
+
+impl TryFrom<FileFilteringSpec> for FileFilteringOpt {
+    type Error = anyhow::Error;
+
+    fn try_from(spec: FileFilteringSpec) -> Result<Self> {
+        let (exclude, exclude_globs) = match (spec.exclude, spec.exclude_globs) {
+            (None, None) => (Vec::new(), DEFAULT_EXCLUDE_GLOBS.iter().map(|g| g.to_string()).collect()),
+            (exclude, exclude_globs) => (exclude.unwrap_or_default(), exclude_globs.unwrap_or_default()),
+        };
+        let mut file_filters = FileFilteringOpt::with_globs(
+            &exclude.iter().map(String::as_str).collect::<Vec<_>>(),
+            &exclude_globs.iter().map(String::as_str).collect::<Vec<_>>(),
+            &spec.include.iter().map(String::as_str).collect::<Vec<_>>(),
+            &spec.include_globs.iter().map(String::as_str).collect::<Vec<_>>(),
+        )?;
+        file_filters.attributes = spec.attributes;
+        Ok(file_filters)
+    }
+}
+
+impl Default for FileFilteringOpt {
+    fn default() -> Self {
+        FileFilteringOpt::with_globs(&[], DEFAULT_EXCLUDE_GLOBS, &[], &[])
+            .expect("invalid default exclude globs")
+    }
+}
+
 impl FileFilteringOpt {
     pub fn new(exclude_patterns: &[&str], include_patterns: &[&str]) -> FileFilteringOpt {
         FileFilteringOpt {
             exclude_paths: FileFilteringOpt::vec_to_regex(exclude_patterns)
                 .expect("invalid exclude path regex"),
             include_paths: FileFilteringOpt::vec_to_regex(include_patterns)
-                .expect("invalid include path regex")
+                .expect("invalid include path regex"),
+            attributes: AttributesScope::None,
+        }
+    }
+
+    pub fn try_new(exclude_patterns: &[&str], include_patterns: &[&str]) -> Result<FileFilteringOpt> {
+        Ok(FileFilteringOpt {
+            exclude_paths: FileFilteringOpt::vec_to_regex(exclude_patterns)
+                .map_err(|e| anyhow!("invalid exclude path regex: {}", e))?,
+            include_paths: FileFilteringOpt::vec_to_regex(include_patterns)
+                .map_err(|e| anyhow!("invalid include path regex: {}", e))?,
+            attributes: AttributesScope::None,
+        })
+    }
+
+    /// Like `try_new`, with gitignore-style globs next to the regexes.
+    /// Empty include patterns accept all files, empty exclude patterns reject none.
+    pub fn with_globs(
+        exclude_patterns: &[&str],
+        exclude_globs: &[&str],
+        include_patterns: &[&str],
+        include_globs: &[&str],
+    ) -> Result<FileFilteringOpt> {
+        let exclude = FileFilteringOpt::join_globs(exclude_patterns, exclude_globs, r"a^");
+        let include = FileFilteringOpt::join_globs(include_patterns, include_globs, r".*");
+        FileFilteringOpt::try_new(
+            &exclude.iter().map(String::as_str).collect::<Vec<_>>(),
+            &include.iter().map(String::as_str).collect::<Vec<_>>(),
+        )
+    }
+
+    fn join_globs(patterns: &[&str], globs: &[&str], empty: &str) -> Vec<String> {
+        let mut regexes = patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        regexes.extend(globs.iter().map(|g| glob_to_regex(g)));
+        if regexes.is_empty() {
+            regexes.push(empty.to_string());
         }
+        regexes
+    }
+
+    /// Also excludes the files matching the given regexes.
+    pub fn exclude_more(&self, patterns: &[String]) -> Result<FileFilteringOpt> {
+        let mut exclude = vec![self.exclude_paths.as_str()];
+        exclude.extend(patterns.iter().map(String::as_str));
+        let mut file_filters = FileFilteringOpt::try_new(&exclude, &[self.include_paths.as_str()])?;
+        file_filters.attributes = self.attributes;
+        Ok(file_filters)
     }
 
     fn vec_to_regex(regex_vec: &[&str]) -> std::result::Result<Regex, Error> {
@@ -111,27 +420,224 @@ impl FileFilteringOpt {
     }
 }
 
+/// Translates a gitignore-style glob into a regex matching repository paths.
+/// Globs without a slash match at any depth, and a trailing slash only matches directories.
+pub fn glob_to_regex(glob: &str) -> String {
+    let glob = glob.trim();
+    let dir_only = glob.ends_with('/');
+    let glob = glob.trim_end_matches('/');
+    let mut regex = String::from(if glob.contains('/') { "^" } else { "(^|/)" });
+    let chars = glob.trim_start_matches('/').chars().collect::<Vec<_>>();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                if chars.get(i + 2) == Some(&'/') {
+                    regex.push_str("(.*/)?");
+                    i += 1;
+                } else {
+                    regex.push_str(".*");
+                }
+                i += 1;
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => match chars[i..].iter().position(|c| *c == ']') {
+                Some(end) if end > 1 => {
+                    let class = chars[i + 1..i + end].iter().collect::<String>();
+                    match class.strip_prefix('!') {
+                        Some(negated) => regex.push_str(&format!("[^{}]", negated)),
+                        None => regex.push_str(&format!("[{}]", class)),
+                    }
+                    i += end;
+                }
+                _ => regex.push_str(r"\["),
+            },
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                regex.push_str(&regex::escape(&chars[i].to_string()));
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    regex.push_str(if dir_only { "/" } else { "(/|$)" });
+    regex
+}
+
+/// Reads exclusion patterns from a gitignore-style file, one glob per line.
+/// Lines starting with `regex:` hold a regex instead, and `#` starts a comment.
+pub fn read_ignore_file(path: &Path) -> Result<Vec<String>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow!("cannot read {}: {}", path.display(), e))?;
+    let mut patterns = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('!') {
+            warn!("Ignoring negated pattern {} in {}, negations are not supported", line, path.display());
+        } else if let Some(regex) = line.strip_prefix("regex:") {
+            patterns.push(regex.trim().to_string());
+        } else {
+            patterns.push(glob_to_regex(line));
+        }
+    }
+    Ok(patterns)
+}
+
 pub trait BetterGit {
     fn mine_objects(&self, filters: &CommitFilteringOpt) -> Result<Vec<Object>>;
-    fn sample_commits<'repo>(objects: Vec<Object<'repo>>, binning: &DateGrouping) -> Vec<Object<'repo>>;
 
     fn diff(&self, parent: &Object, child: &Object) -> Result<Diff>;
-    fn diffs(&self, objects: &Vec<Object>, file_filters: &FileFilteringOpt) -> GroupedBetterDiffs;
+    fn diffs(&self, objects: &Vec<Object>, options: &BetterGitOpt) -> Result<GroupedBetterDiffs>;
 
     fn mine_diffs(&self, options: &BetterGitOpt) -> Result<GroupedBetterDiffs>;
+
+    fn attribute_files_at(&self, object: Option<&Object>) -> AttributeFiles;
+    fn attributes_from(&self, files: &AttributeFiles) -> GitAttributes;
+
+    fn mailmap_for(&self, filters: &CommitFilteringOpt) -> Option<Mailmap>;
+
+    fn tag_ranges(&self, pattern: Option<&str>) -> Result<Vec<String>>;
+
+    fn files_at(&self, revision: &str) -> Result<HashSet<String>>;
+}
+
+/// An entity changed by a file delta, and the lines inserted and deleted in it.
+struct ChangedEntity {
+    old: String,
+    new: String,
+    insertions: usize,
+    deletions: usize,
+}
+
+/// The file changed by a delta or, when a symbol parser is given, the functions, methods and types
+/// touched by it as `path::Type::method` entities. Changed lines outside of any definition are
+/// attributed to the file itself.
+fn changed_entities(repo: &Repository, diff: &Diff, idx: usize, parser: Option<&mut SymbolParser>, old_file: &str, new_file: &str) -> Vec<ChangedEntity> {
+    let file_only = |insertions, deletions| vec![ChangedEntity {
+        old: old_file.to_string(),
+        new: new_file.to_string(),
+        insertions,
+        deletions,
+    }];
+    let patch = match Patch::from_diff(diff, idx) {
+        Ok(Some(patch)) => patch,
+        _ => return file_only(0, 0),
+    };
+    let (_, insertions, deletions) = patch.line_stats().unwrap_or((0, 0, 0));
+    let parser = match parser {
+        Some(parser) if !patch.delta().flags().is_binary() => parser,
+        _ => return file_only(insertions, deletions),
+    };
+    let delta = patch.delta();
+    let mut symbols_of = |id: Oid, path: &str| match repo.find_blob(id) {
+        Ok(blob) if !id.is_zero() => parser.symbols(path, blob.content()),
+        _ => Vec::new(),
+    };
+    let old_symbols = symbols_of(delta.old_file().id(), old_file);
+    let new_symbols = symbols_of(delta.new_file().id(), new_file);
+    let mut entities: Vec<ChangedEntity> = Vec::new();
+    for hunk in 0..patch.num_hunks() {
+        for line in 0..patch.num_lines_in_hunk(hunk).unwrap_or(0) {
+            let line = match patch.line_in_hunk(hunk, line) {
+                Ok(line) => line,
+                Err(_) => continue,
+            };
+            let (symbol, inserted) = match (line.origin(), line.old_lineno(), line.new_lineno()) {
+                ('-', Some(lineno), _) => (enclosing_symbol(&old_symbols, lineno as usize), false),
+                ('+', _, Some(lineno)) => (enclosing_symbol(&new_symbols, lineno as usize), true),
+                _ => continue,
+            };
+            let (old, new) = match symbol {
+                Some(symbol) => (format!("{}::{}", old_file, symbol.name), format!("{}::{}", new_file, symbol.name)),
+                None => (old_file.to_string(), new_file.to_string()),
+            };
+            let entity = match entities.iter().position(|e| e.new == new) {
+                Some(i) => &mut entities[i],
+                None => {
+                    entities.push(ChangedEntity { old, new, insertions: 0, deletions: 0 });
+                    entities.last_mut().unwrap()
+                }
+            };
+            if inserted {
+                entity.insertions += 1;
+            } else {
+                entity.deletions += 1;
+            }
+        }
+    }
+    if entities.is_empty() {
+        return file_only(insertions, deletions);
+    }
+    entities
+}
+
+/// Pushes the commits of a revision range to the revwalk, and hides the ones excluded by it.
+fn push_range(repo: &Repository, revwalk: &mut Revwalk, range: &str) -> Result<()> {
+    let commit_id = |o: Option<&Object>| -> Result<Oid> {
+        match o {
+            Some(o) => Ok(o.peel_to_commit()?.id()),
+            None => bail!("invalid revision range {}", range),
+        }
+    };
+    if let Some(hidden) = range.strip_prefix('^') {
+        return match repo.revparse_single(hidden) {
+            Ok(obj) => Ok(revwalk.hide(commit_id(Some(&obj))?)?),
+            Err(e) => bail!("cannot find revision {}: {}", hidden, e.message()),
+        };
+    }
+    let revspec = match repo.revparse(range) {
+        Ok(revspec) => revspec,
+        Err(e) => bail!("cannot parse revision range {}: {}", range, e.message()),
+    };
+    let from = commit_id(revspec.from())?;
+    if revspec.mode().contains(RevparseMode::MERGE_BASE) {
+        let to = commit_id(revspec.to())?;
+        revwalk.push(from)?;
+        revwalk.push(to)?;
+        revwalk.hide(repo.merge_base(from, to)?)?;
+    } else if revspec.mode().contains(RevparseMode::RANGE) {
+        revwalk.hide(from)?;
+        revwalk.push(commit_id(revspec.to())?)?;
+    } else {
+        revwalk.push(from)?;
+    }
+    Ok(())
 }
 
 impl BetterGit for Repository {
     fn mine_objects(&self, filters: &CommitFilteringOpt) -> Result<Vec<Object>> {
         let mut revwalk = self.revwalk()?;
         revwalk.set_sorting(Sort::REVERSE | Sort::TIME | Sort::TOPOLOGICAL)?;
-        let head = match self.revparse_single(filters.branch.as_str()) {
-            Ok(head) => head,
-            Err(e) => return Err(anyhow!("cannot find branch {}: {}", filters.branch, e.message())),
-        };
-        revwalk.push(head.id())?;
+        if filters.ranges.is_empty() {
+            let head = match self.revparse_single(filters.branch.as_str()) {
+                Ok(head) => head,
+                Err(e) => return Err(anyhow!("cannot find branch {}: {}", filters.branch, e.message())),
+            };
+            revwalk.push(head.id())?;
+        }
+        for range in filters.ranges.iter() {
+            push_range(self, &mut revwalk, range)?;
+        }
+        for reference in filters.refs.iter() {
+            if reference.contains(['*', '?', '[']) {
+                revwalk.push_glob(reference)?;
+                continue;
+            }
+            match self.revparse_single(reference) {
+                Ok(obj) => revwalk.push(obj.peel_to_commit()?.id())?,
+                Err(e) => bail!("cannot find reference {}: {}", reference, e.message()),
+            }
+        }
+        if filters.all_refs {
+            revwalk.push_glob("*")?;
+        }
         let until = filters.until.timestamp();
         let since = filters.since.timestamp();
+        let commit_filter = CommitFilter::new(filters)?;
+        let mailmap = self.mailmap_for(filters);
         let commits: Vec<Object> = revwalk
             .into_iter()
             .filter_map(|oid| oid.ok())
@@ -140,25 +646,12 @@ impl BetterGit for Repository {
                 let commit = (&o).as_commit().expect("not a commit");
                 let commit_ts = commit.time().seconds();
                 commit_ts > since && commit_ts < until
+                    && commit_filter.matches(&BetterCommit::from(commit, mailmap.as_ref()), commit.message().unwrap_or(""))
             })
             .collect();
-        let commits = Repository::sample_commits(commits, &filters.binning);
         Ok(commits)
     }
 
-    fn sample_commits<'repo>(objects: Vec<Object<'repo>>, binning: &DateGrouping) -> Vec<Object<'repo>> {
-        objects.into_iter()
-            .map(|o| {
-                let commit = o.as_commit().expect("not a commit");
-                let time = Utc.timestamp_opt(commit.time().seconds(), 0).unwrap();
-                (o, binning.get_group(&time))
-            })
-            .sorted_by(|x, y| Ord::cmp(&x.1, &y.1))
-            .dedup_by(|x, y| x.1 == y.1)
-            .map(|(o, _)| o)
-            .collect::<Vec<Object<'repo>>>()
-    }
-
     fn diff(&self, parent: &Object, child: &Object) -> Result<Diff> {
         let p_obj = parent
             .peel(ObjectType::Tree)
@@ -167,15 +660,20 @@ impl BetterGit for Repository {
         let p_tree = p_obj.as_tree().unwrap();
         let c_tree = c_obj.as_tree().unwrap();
 
-        Ok(self.diff_tree_to_tree(Some(p_tree), Some(c_tree), None)?)
+        let mut diff = self.diff_tree_to_tree(Some(p_tree), Some(c_tree), None)?;
+        diff.find_similar(None)?;
+        Ok(diff)
     }
 
-    fn diffs(&self, objects: &Vec<Object>, file_filters: &FileFilteringOpt) -> GroupedBetterDiffs {
+    /// Diffs each commit with its first parent and merges the diffs of the commits in the same transaction,
+    /// so a file changed and reverted within a transaction still counts as changed, and the changes of
+    /// commits filtered out are never attributed to the commits kept. Root commits have no parent to diff
+    /// against and merge commits are skipped, as their first-parent diff repeats the merged commits.
+    fn diffs(&self, objects: &Vec<Object>, options: &BetterGitOpt) -> Result<GroupedBetterDiffs> {
+        let file_filters = &options.file_filters;
+        let mut transactions = TransactionKeys::new(&options.commit_filters)?;
+        let mailmap = self.mailmap_for(&options.commit_filters);
         let mut diffs = GroupedBetterDiffs::new();
-        let rcs: Vec<Rc<BetterCommit>> = objects.iter()
-            .map(|o| o.as_commit().expect("not a commit"))
-            .map(|cmt| Rc::new(BetterCommit::from(cmt)))
-            .collect();
         let mut all_files = HashMap::<Rc<String>, Rc<String>>::new();
         let mut get_rc = |s: String| {
             if !all_files.contains_key(&s) {
@@ -185,50 +683,157 @@ impl BetterGit for Repository {
             }
             return all_files.get(&s).unwrap().clone();
         };
-        for i in 0..(objects.len() - 1) {
-            let parent = &objects[i];
-            let child = &objects[i + 1];
-            let diff = match self.diff(parent, child) {
+        let mut attribute_files = match file_filters.attributes {
+            AttributesScope::None => AttributeFiles::new(),
+            AttributesScope::Head => self.attribute_files_at(objects.last()),
+            AttributesScope::Commit => self.attribute_files_at(objects.first()),
+        };
+        let mut attributes = self.attributes_from(&attribute_files);
+        let mut symbols = match options.granularity {
+            Granularity::Files => None,
+            Granularity::Symbols => Some(SymbolParser::new()),
+        };
+        for child in objects.iter() {
+            let commit = child.as_commit().expect("not a commit");
+            if commit.parent_count() != 1 {
+                continue;
+            }
+            let parent = match commit.parent(0) {
+                Ok(p) => p,
+                Err(_) => continue,
+            };
+            let diff = match self.diff(parent.as_object(), child) {
                 Ok(d) => d,
                 Err(_) => {
                     debug!("cannot calculate diff between [{}] and [{}]", parent.id(), child.id());
                     continue;
                 }
             };
-            let parent_rc = rcs[i].clone();
-            let child_rc = rcs[i + 1].clone();
-
-            let mut b_diff = BetterDiff::new(parent_rc, child_rc);
+            if file_filters.attributes == AttributesScope::Commit {
+                // compare the files rather than look for them in the diff, since merges
+                // and filtered out commits change attributes without being diffed
+                let files = self.attribute_files_at(Some(child));
+                if files != attribute_files {
+                    attributes = self.attributes_from(&files);
+                    attribute_files = files;
+                }
+            }
+            let child_rc = Rc::new(BetterCommit::from(commit, mailmap.as_ref()));
+            let b_diff = diffs.entry(transactions.key(&child_rc, commit.message().unwrap_or("")))
+                .or_insert_with(|| {
+                    let parent_rc = Rc::new(BetterCommit::from(&parent, mailmap.as_ref()));
+                    BetterDiff::new(parent_rc, child_rc.clone())
+                });
+            b_diff.child = child_rc.clone();
+            b_diff.commits.push(child_rc.clone());
             diff.deltas()
-                .for_each(|d| {
+                .enumerate()
+                .for_each(|(idx, d)| {
                     let old_file = d.old_file().path()
                         .map(|p| p.to_str().unwrap())
                         .unwrap_or("<unknown>")
                         .to_string();
-                    if file_filters.matches(&old_file) {
-                        let old_file = get_rc(old_file);
-                        b_diff.old_files.push(old_file);
+                    let kind = ChangeKind::from_delta(d.status());
+                    if options.exclude_changes.contains(&kind) {
+                        return;
+                    }
+                    if file_filters.matches(&old_file) && !attributes.excludes(&old_file) {
                         let new_file = d.new_file().path()
                             .map(|p| p.to_str().unwrap())
                             .unwrap_or("<unknown>")
                             .to_string();
-                        let new_file = get_rc(new_file);
-                        b_diff.new_files.push(new_file);
+                        let parser = symbols.as_mut().filter(|_| SymbolParser::supports(&new_file));
+                        for entity in changed_entities(self, &diff, idx, parser, &old_file, &new_file) {
+                            let new_file = get_rc(entity.new);
+                            b_diff.contributions.push(Contribution {
+                                file: new_file.clone(),
+                                commit: child_rc.clone(),
+                                insertions: entity.insertions,
+                                deletions: entity.deletions,
+                            });
+                            b_diff.old_files.push(get_rc(entity.old));
+                            b_diff.new_files.push(new_file);
+                            b_diff.insertions.push(entity.insertions);
+                            b_diff.deletions.push(entity.deletions);
+                            b_diff.kinds.push(kind);
+                        }
                     }
                 });
-            diffs.insert(b_diff.child.when.clone(), b_diff);
         }
-        diffs
+        diffs.values_mut().for_each(BetterDiff::dedup_files);
+        Ok(diffs)
     }
 
     fn mine_diffs(&self, options: &BetterGitOpt) -> Result<GroupedBetterDiffs> {
         let objs = self.mine_objects(&options.commit_filters)?;
         debug!("Found {} total commits", objs.len());
-        Ok(self.diffs(&objs, &options.file_filters))
+        self.diffs(&objs, options)
+    }
+
+    fn attribute_files_at(&self, object: Option<&Object>) -> AttributeFiles {
+        let tree = match object.map(|o| o.peel_to_tree()) {
+            Some(Ok(tree)) => tree,
+            _ => return AttributeFiles::new(),
+        };
+        GitAttributes::files_in(&tree).unwrap_or_else(|e| {
+            warn!("cannot find {} files: {}", ATTRIBUTES_FILE_NAME, e);
+            AttributeFiles::new()
+        })
+    }
+
+    fn attributes_from(&self, files: &AttributeFiles) -> GitAttributes {
+        GitAttributes::from_files(self, files).unwrap_or_else(|e| {
+            warn!("cannot read {} files: {}", ATTRIBUTES_FILE_NAME, e);
+            GitAttributes::default()
+        })
+    }
+
+    /// Ranges between consecutive tags matching the glob, ordered by the date of the tagged commits.
+    fn tag_ranges(&self, pattern: Option<&str>) -> Result<Vec<String>> {
+        let names = self.tag_names(pattern)?;
+        let tags = names.iter()
+            .flatten()
+            .filter_map(|name| {
+                let commit = self.revparse_single(name).ok()?.peel_to_commit().ok()?;
+                Some((commit.time().seconds(), name.to_string()))
+            })
+            .sorted()
+            .map(|(_, name)| name)
+            .collect::<Vec<_>>();
+        Ok(tags.iter().tuple_windows().map(|(from, to)| format!("{}..{}", from, to)).collect())
+    }
+
+    fn mailmap_for(&self, filters: &CommitFilteringOpt) -> Option<Mailmap> {
+        if !filters.mailmap {
+            return None;
+        }
+        match self.mailmap() {
+            Ok(mailmap) => Some(mailmap),
+            Err(e) => {
+                warn!("cannot read .mailmap: {}", e);
+                None
+            }
+        }
+    }
+
+    fn files_at(&self, revision: &str) -> Result<HashSet<String>> {
+        let tree = match self.revparse_single(revision) {
+            Ok(obj) => obj.peel_to_tree()?,
+            Err(e) => bail!("cannot find revision {}: {}", revision, e.message()),
+        };
+        let mut files = HashSet::new();
+        tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() == Some(ObjectType::Blob) {
+                files.insert(format!("{}{}", dir, entry.name().unwrap_or("")));
+            }
+            TreeWalkResult::Ok
+        })?;
+        Ok(files)
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum DateGrouping {
     None,
     Daily,
@@ -279,14 +884,474 @@ impl DateGrouping {
     }
 }
 
+/// How a file changed in a commit, or in the commits of a transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+    Renamed,
+}
+
+impl FromStr for ChangeKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "added" => Ok(ChangeKind::Added),
+            "modified" => Ok(ChangeKind::Modified),
+            "deleted" => Ok(ChangeKind::Deleted),
+            "renamed" => Ok(ChangeKind::Renamed),
+            _ => bail!("cannot parse ChangeKind from {}", s)
+        }
+    }
+}
+
+impl Display for ChangeKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Deleted => "deleted",
+            ChangeKind::Renamed => "renamed",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl ChangeKind {
+    fn from_delta(delta: Delta) -> ChangeKind {
+        match delta {
+            Delta::Added | Delta::Copied => ChangeKind::Added,
+            Delta::Deleted => ChangeKind::Deleted,
+            Delta::Renamed => ChangeKind::Renamed,
+            _ => ChangeKind::Modified,
+        }
+    }
+
+    /// The kind of two consecutive changes to the same file, e.g. a file added and then modified was added.
+    fn then(self, later: ChangeKind) -> ChangeKind {
+        match (self, later) {
+            (_, ChangeKind::Deleted) => ChangeKind::Deleted,
+            (ChangeKind::Deleted, _) => ChangeKind::Modified,
+            (ChangeKind::Added, _) => ChangeKind::Added,
+            (ChangeKind::Renamed, ChangeKind::Modified) => ChangeKind::Renamed,
+            (_, later) => later,
+        }
+    }
+}
+
+/// Transactions of one day each, from `(day, author, file, churn)` changes starting on 2023-01-01,
+/// with one commit per day and author.
+#[cfg(test)]
+pub(crate) fn diffs_with_churn(changes: &[(i64, &str, &str, usize)]) -> GroupedBetterDiffs {
+    let start = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+    let mut diffs = GroupedBetterDiffs::new();
+    for (day, author, file, churn) in changes.iter() {
+        let commit = Rc::new(BetterCommit {
+            sha1: format!("{}{}", day, author),
+            author: author.to_string(),
+            email: format!("{}@example.com", author.to_lowercase()),
+            when: start + chrono::Duration::days(*day),
+            summary: "change".to_string(),
+        });
+        let diff = diffs.entry(commit.when).or_insert_with(|| BetterDiff::new(commit.clone(), commit.clone()));
+        let commit = match diff.commits.iter().find(|c| c.sha1 == commit.sha1) {
+            Some(c) => c.clone(),
+            None => {
+                diff.commits.push(commit.clone());
+                commit
+            }
+        };
+        let file = Rc::new(file.to_string());
+        diff.child = commit.clone();
+        diff.old_files.push(file.clone());
+        diff.new_files.push(file.clone());
+        diff.insertions.push(*churn);
+        diff.deletions.push(0);
+        diff.kinds.push(ChangeKind::Modified);
+        diff.contributions.push(Contribution { file, commit, insertions: *churn, deletions: 0 });
+    }
+    diffs.values_mut().for_each(BetterDiff::dedup_files);
+    diffs
+}
+
+/// Like `diffs_with_churn`, changing one line of each file.
+#[cfg(test)]
+pub(crate) fn diffs_of(changes: &[(i64, &str, &str)]) -> GroupedBetterDiffs {
+    diffs_with_churn(&changes.iter().map(|(day, author, file)| (*day, *author, *file, 1)).collect::<Vec<_>>())
+}
+
 #[cfg(test)]
 mod tests {
     use std::rc::Rc;
 
     use chrono::{TimeZone, Utc};
     use git2::Repository;
+    use itertools::Itertools;
+
+    use crate::bettergit::{BetterCommit, BetterGit, BetterGitOpt, ChangeKind, CommitFilter, CommitFilteringOpt, DateGrouping, FileFilteringOpt, glob_to_regex};
+    use crate::gitattributes::AttributesScope;
+    use crate::symbols::Granularity;
+    use crate::transactions::TransactionGrouping;
+
+    fn commit_as(repo: &Repository, name: &str, email: &str, file: &str) {
+        let path = repo.workdir().unwrap().join(file);
+        let content = std::fs::read_to_string(&path).unwrap_or_default() + name + "\n";
+        commit_content(repo, name, email, file, &content);
+    }
+
+    fn commit_content(repo: &Repository, name: &str, email: &str, file: &str, content: &str) {
+        let path = repo.workdir().unwrap().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, content).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new(file)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now(name, email).unwrap();
+        let parents = repo.head().ok().map(|h| h.peel_to_commit().unwrap()).into_iter().collect::<Vec<_>>();
+        repo.commit(Some("HEAD"), &signature, &signature, "change", &tree, &parents.iter().collect::<Vec<_>>()).unwrap();
+    }
+
+    #[test]
+    fn test_message_filters() {
+        let commit = |summary: &str| BetterCommit {
+            sha1: String::new(),
+            author: "Alice".to_string(),
+            email: "alice@example.com".to_string(),
+            when: Utc::now(),
+            summary: summary.to_string(),
+        };
+        let matches = |opts: &CommitFilteringOpt, summary: &str| {
+            CommitFilter::new(opts).unwrap().matches(&commit(summary), &format!("{}\n\nRefs PROJ-1", summary))
+        };
+        let mut opts = CommitFilteringOpt {
+            include_types: vec!["fix".to_string()],
+            exclude_scopes: vec!["docs".to_string()],
+            ..CommitFilteringOpt::default()
+        };
+        assert!(matches(&opts, "fix(parser): handle empty input"));
+        assert!(matches(&opts, "Fix: handle empty input"));
+        assert!(!matches(&opts, "fix(docs): typo"));
+        assert!(!matches(&opts, "feat: new parser"));
+        assert!(!matches(&opts, "handle empty input"));
+
+        opts = CommitFilteringOpt {
+            exclude_types: vec!["chore".to_string(), "style".to_string()],
+            message_regex: Some("proj-1".to_string()),
+            exclude_message_regex: Some("^wip".to_string()),
+            ..CommitFilteringOpt::default()
+        };
+        assert!(matches(&opts, "feat: new parser"));
+        assert!(matches(&opts, "new parser"));
+        assert!(!matches(&opts, "chore: bump deps"));
+        assert!(!matches(&opts, "WIP new parser"));
+        assert!(!CommitFilter::new(&opts).unwrap().matches(&commit("feat: new parser"), "feat: new parser"));
+    }
+
+    #[test]
+    fn test_multiple_refs() {
+        let dir = std::env::temp_dir().join("ccan-test-refs");
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Repository::init(&dir).unwrap();
+        commit_as(&repo, "Alice", "alice@example.com", "a.rs");
+        commit_as(&repo, "Alice", "alice@example.com", "a.rs");
+        let main = repo.head().unwrap().peel_to_commit().unwrap();
+        for (branch, file) in [("release/1.0", "r1.rs"), ("release/2.0", "r2.rs"), ("feature", "f.rs")] {
+            repo.branch(branch, &main, true).unwrap();
+            repo.set_head(&format!("refs/heads/{}", branch)).unwrap();
+            repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force())).unwrap();
+            commit_as(&repo, "Alice", "alice@example.com", file);
+        }
+        let mut filters = CommitFilteringOpt { branch: main.id().to_string(), ..CommitFilteringOpt::default() };
+        assert_eq!(2, repo.mine_objects(&filters).unwrap().len());
+        filters.refs = vec!["refs/heads/release/*".to_string(), "release/1.0".to_string()];
+        assert_eq!(4, repo.mine_objects(&filters).unwrap().len());
+        filters.all_refs = true;
+        assert_eq!(5, repo.mine_objects(&filters).unwrap().len());
+        filters.refs = vec!["missing".to_string()];
+        assert!(repo.mine_objects(&filters).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_revision_ranges() {
+        let dir = std::env::temp_dir().join("ccan-test-ranges");
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Repository::init(&dir).unwrap();
+        for (i, tag) in ["v1.0", "v1.1", "", "v2.0", ""].iter().enumerate() {
+            commit_as(&repo, "Alice", "alice@example.com", &format!("{}.rs", i));
+            if !tag.is_empty() {
+                let head = repo.head().unwrap().peel(git2::ObjectType::Commit).unwrap();
+                repo.tag_lightweight(tag, &head, false).unwrap();
+            }
+        }
+        let count = |ranges: &[&str]| {
+            let filters = CommitFilteringOpt {
+                ranges: ranges.iter().map(|r| r.to_string()).collect(),
+                ..CommitFilteringOpt::default()
+            };
+            repo.mine_objects(&filters).unwrap().len()
+        };
+        assert_eq!(2, count(&["v1.1..v2.0"]));
+        assert_eq!(1, count(&["v2.0..HEAD"]));
+        assert_eq!(4, count(&["HEAD", "^v1.0"]));
+        assert_eq!(2, count(&["v1.1...v2.0"]));
+        assert_eq!(vec!["v1.0..v1.1", "v1.1..v2.0"], repo.tag_ranges(Some("v*")).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_symbol_granularity() {
+        let dir = std::env::temp_dir().join("ccan-test-symbols");
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Repository::init(&dir).unwrap();
+        let source = |area: &str, main: &str| format!(
+            "struct Shape {{}}\n\nimpl Shape {{\n    fn area(&self) -> u32 {{\n        {}\n    }}\n}}\n\nfn main() {{\n    {}\n}}\n",
+            area, main
+        );
+        commit_content(&repo, "Alice", "alice@example.com", "src/shape.rs", &source("1", "let _ = 1;"));
+        commit_content(&repo, "Alice", "alice@example.com", "src/shape.rs", &source("2", "let _ = 1;"));
+        commit_content(&repo, "Alice", "alice@example.com", "src/shape.rs", &source("2", "let _ = 2;"));
+        commit_as(&repo, "Alice", "alice@example.com", "notes.cfg");
+
+        let mut opts = BetterGitOpt {
+            commit_filters: CommitFilteringOpt { transactions: TransactionGrouping::Sessions, ..CommitFilteringOpt::default() },
+            file_filters: FileFilteringOpt::accept_all(),
+            granularity: Granularity::Symbols,
+            ..BetterGitOpt::default()
+        };
+        let changed = |opts: &BetterGitOpt| {
+            let mut entities = repo.mine_diffs(opts).unwrap().into_values()
+                .flat_map(|d| d.new_files.into_iter().map(|f| f.to_string()))
+                .collect::<Vec<_>>();
+            entities.sort();
+            entities
+        };
+        assert_eq!(vec!["notes.cfg", "src/shape.rs::Shape::area", "src/shape.rs::main"], changed(&opts));
+        let diff = repo.mine_diffs(&opts).unwrap().into_values().next().unwrap();
+        let churn = diff.new_files.iter().enumerate()
+            .map(|(i, f)| (f.to_string(), diff.insertions[i], diff.deletions[i]))
+            .sorted()
+            .collect::<Vec<_>>();
+        // the root commit is skipped, then one line of each function is rewritten and notes.cfg is added
+        assert_eq!(("notes.cfg".to_string(), 1, 0), churn[0]);
+        assert_eq!(("src/shape.rs::Shape::area".to_string(), 1, 1), churn[1]);
+        assert_eq!(("src/shape.rs::main".to_string(), 1, 1), churn[2]);
+        opts.granularity = Granularity::Files;
+        assert_eq!(vec!["notes.cfg", "src/shape.rs"], changed(&opts));
+    }
+
+    #[test]
+    fn test_change_kinds() {
+        let dir = std::env::temp_dir().join("ccan-test-kinds");
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Repository::init(&dir).unwrap();
+        commit_as(&repo, "Alice", "alice@example.com", "a.rs");
+        commit_content(&repo, "Alice", "alice@example.com", "b.rs", "fn b() {}\nfn c() {}\nfn d() {}\n");
+        commit_as(&repo, "Alice", "alice@example.com", "a.rs");
+        std::fs::rename(dir.join("b.rs"), dir.join("c.rs")).unwrap();
+        let mut index = repo.index().unwrap();
+        index.remove_path(std::path::Path::new("b.rs")).unwrap();
+        index.write().unwrap();
+        commit_content(&repo, "Alice", "alice@example.com", "c.rs", "fn b() {}\nfn c() {}\nfn d() {}\n");
+        std::fs::remove_file(dir.join("a.rs")).unwrap();
+        let mut index = repo.index().unwrap();
+        index.remove_path(std::path::Path::new("a.rs")).unwrap();
+        index.write().unwrap();
+        commit_content(&repo, "Alice", "alice@example.com", "d.rs", "");
+
+        let mut opts = BetterGitOpt {
+            commit_filters: CommitFilteringOpt { transactions: TransactionGrouping::Issues, ..CommitFilteringOpt::default() },
+            file_filters: FileFilteringOpt::accept_all(),
+            ..BetterGitOpt::default()
+        };
+        let changed = |opts: &BetterGitOpt| {
+            repo.mine_diffs(opts).unwrap().into_values()
+                .flat_map(|d| d.new_files.into_iter().zip(d.kinds).map(|(f, k)| (f.to_string(), k)))
+                .sorted_by_key(|(f, k)| (f.clone(), k.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let file = |f: &str, k: ChangeKind| (f.to_string(), k);
+        assert_eq!(
+            vec![
+                file("a.rs", ChangeKind::Deleted), file("a.rs", ChangeKind::Modified), file("b.rs", ChangeKind::Added),
+                file("c.rs", ChangeKind::Renamed), file("d.rs", ChangeKind::Added),
+            ],
+            changed(&opts)
+        );
+        opts.exclude_changes = vec![ChangeKind::Added, ChangeKind::Deleted];
+        assert_eq!(vec![file("a.rs", ChangeKind::Modified), file("c.rs", ChangeKind::Renamed)], changed(&opts));
+
+        assert_eq!(ChangeKind::Added, ChangeKind::Added.then(ChangeKind::Modified));
+        assert_eq!(ChangeKind::Deleted, ChangeKind::Renamed.then(ChangeKind::Deleted));
+        assert!(repo.files_at("HEAD").unwrap().contains("c.rs"));
+        assert!(!repo.files_at("HEAD").unwrap().contains("a.rs"));
+        let ranges = |ranges: &[&str]| CommitFilteringOpt {
+            ranges: ranges.iter().map(|r| r.to_string()).collect(),
+            ..CommitFilteringOpt::default()
+        }.head();
+        assert_eq!("HEAD", ranges(&[]));
+        assert_eq!("v2", ranges(&["v1..v2", "^v0"]));
+        assert_eq!("main", ranges(&["dev...main"]));
+        assert_eq!("HEAD", ranges(&["v1.."]));
+    }
+
+    #[test]
+    fn test_attributes_per_commit() {
+        let dir = std::env::temp_dir().join("ccan-test-attributes");
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Repository::init(&dir).unwrap();
+        commit_as(&repo, "Alice", "alice@example.com", "a.rs");
+        commit_as(&repo, "Alice", "alice@example.com", "gen/x.rs");
+        // only changes attributes, and is filtered out with Bob's commits
+        commit_content(&repo, "Bob", "bob@example.com", ".gitattributes", "gen/** linguist-generated\n");
+        commit_as(&repo, "Alice", "alice@example.com", "gen/x.rs");
+        commit_as(&repo, "Alice", "alice@example.com", "a.rs");
 
-    use crate::bettergit::{BetterGit, BetterGitOpt, CommitFilteringOpt, DateGrouping, FileFilteringOpt};
+        let mut opts = BetterGitOpt {
+            commit_filters: CommitFilteringOpt {
+                transactions: TransactionGrouping::Issues,
+                exclude_authors: vec!["Bob".to_string()],
+                ..CommitFilteringOpt::default()
+            },
+            file_filters: FileFilteringOpt::accept_all(),
+            ..BetterGitOpt::default()
+        };
+        opts.file_filters.attributes = AttributesScope::Commit;
+        let changed = |opts: &BetterGitOpt| {
+            repo.mine_diffs(opts).unwrap().into_values()
+                .flat_map(|d| d.new_files.into_iter().zip(d.kinds).map(|(f, k)| (f.to_string(), k)))
+                .sorted_by_key(|(f, k)| (f.clone(), k.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let file = |f: &str, k: ChangeKind| (f.to_string(), k);
+        // gen/x.rs is generated from the commit after Bob's
+        assert_eq!(vec![file("a.rs", ChangeKind::Modified), file("gen/x.rs", ChangeKind::Added)], changed(&opts));
+        opts.file_filters.attributes = AttributesScope::None;
+        assert_eq!(
+            vec![file("a.rs", ChangeKind::Modified), file("gen/x.rs", ChangeKind::Added), file("gen/x.rs", ChangeKind::Modified)],
+            changed(&opts)
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_binned_diffs() {
+        let dir = std::env::temp_dir().join("ccan-test-binning");
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Repository::init(&dir).unwrap();
+        commit_content(&repo, "Alice", "alice@example.com", "root.rs", "root");
+        commit_content(&repo, "Alice", "alice@example.com", "a.rs", "a");
+        let base = repo.head().unwrap().peel_to_commit().unwrap();
+        commit_content(&repo, "Alice", "alice@example.com", "b.rs", "b");
+        commit_content(&repo, "Alice", "alice@example.com", "b.rs", "changed");
+        commit_content(&repo, "Alice", "alice@example.com", "b.rs", "b");
+        let signature = git2::Signature::now("Bob", "bob@example.com").unwrap();
+        let with_files = |commit: &git2::Commit, files: &[&str]| {
+            let mut builder = repo.treebuilder(Some(&commit.tree().unwrap())).unwrap();
+            for f in files {
+                builder.insert(f, repo.blob(f.as_bytes()).unwrap(), 0o100644).unwrap();
+            }
+            repo.find_tree(builder.write().unwrap()).unwrap()
+        };
+        let side = repo.commit(None, &signature, &signature, "side", &with_files(&base, &["c.rs"]), &[&base]).unwrap();
+        let side = repo.find_commit(side).unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        // the merge also changes a file of its own, which is lost with the merge
+        let merged = with_files(&head, &["c.rs", "d.rs"]);
+        repo.commit(Some("HEAD"), &signature, &signature, "merge", &merged, &[&head, &side]).unwrap();
+
+        let opts = BetterGitOpt {
+            commit_filters: CommitFilteringOpt {
+                branch: "HEAD".to_string(),
+                binning: DateGrouping::Monthly,
+                ..CommitFilteringOpt::default()
+            },
+            file_filters: FileFilteringOpt::accept_all(),
+            ..BetterGitOpt::default()
+        };
+        let diffs = repo.mine_diffs(&opts).unwrap();
+        assert_eq!(1, diffs.len());
+        let diff = diffs.values().next().unwrap();
+        assert_eq!(5, diff.commits.len());
+        // b.rs is reverted within the bin, but still changed by its commits
+        let files = diff.new_files.iter().map(|f| f.as_str()).sorted().collect::<Vec<_>>();
+        assert_eq!(vec!["a.rs", "b.rs", "c.rs"], files);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_author_filters() {
+        let dir = std::env::temp_dir().join("ccan-test-authors");
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Repository::init(&dir).unwrap();
+        commit_as(&repo, "Alice", "alice@example.com", "a.rs");
+        commit_as(&repo, "Alice", "alice@example.com", "a.rs");
+        commit_as(&repo, "dependabot[bot]", "49699333+dependabot[bot]@users.noreply.github.com", "b.rs");
+        commit_as(&repo, "Bob", "bob@example.com", "c.rs");
+        commit_as(&repo, "bobby", "bob@old.example.com", "d.rs");
+        std::fs::write(dir.join(".mailmap"), "Bob <bob@example.com> <bob@old.example.com>\n").unwrap();
+
+        let mut opts = BetterGitOpt {
+            commit_filters: CommitFilteringOpt { branch: "HEAD".to_string(), ..CommitFilteringOpt::default() },
+            file_filters: FileFilteringOpt::accept_all(),
+            ..BetterGitOpt::default()
+        };
+        let changed = |opts: &BetterGitOpt| {
+            let mut files = repo.mine_diffs(opts).unwrap().into_values()
+                .flat_map(|d| d.new_files.into_iter().map(|f| f.to_string()))
+                .collect::<Vec<_>>();
+            files.sort();
+            files
+        };
+        assert_eq!(vec!["a.rs", "b.rs", "c.rs", "d.rs"], changed(&opts));
+
+        opts.commit_filters.exclude_bots = true;
+        assert_eq!(vec!["a.rs", "c.rs", "d.rs"], changed(&opts));
+
+        opts.commit_filters.include_authors = vec!["^bob <bob@example".to_string()];
+        assert_eq!(vec!["c.rs"], changed(&opts));
+
+        opts.commit_filters.mailmap = true;
+        assert_eq!(vec!["c.rs", "d.rs"], changed(&opts));
+
+        opts.commit_filters.include_authors = Vec::new();
+        opts.commit_filters.exclude_authors = vec!["@example.com>$".to_string()];
+        assert!(changed(&opts).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_glob_filters() {
+        assert_eq!(r"(^|/)[^/]*\.lock(/|$)", glob_to_regex("*.lock"));
+        let filters = FileFilteringOpt::with_globs(
+            &[r"^docs/"],
+            &["*.json", "/build/", "target/**/gen_*.rs", "test?.[!c]s"],
+            &[],
+            &["src/", "*.ts"],
+        ).unwrap();
+        let matches = |p: &str| filters.matches(&p.to_string());
+        assert!(matches("src/main.rs"));
+        assert!(matches("web/app.ts"));
+        assert!(!matches("web/app.js"));
+        assert!(!matches("src/package.json"));
+        assert!(!matches("docs/src/index.ts"));
+        assert!(matches("src/build/main.rs"));
+        assert!(!matches("build/src/main.ts"));
+        assert!(filters.exclude(&"target/a/b/gen_api.rs".to_string()));
+        assert!(filters.exclude(&"target/gen_api.rs".to_string()));
+        assert!(!filters.exclude(&"src/target/gen_api.rs".to_string()));
+        assert!(!matches("src/test1.ts"));
+        assert!(matches("src/test1.cs"));
+
+        let defaults = FileFilteringOpt::default();
+        assert!(defaults.exclude(&"Cargo.lock".to_string()));
+        assert!(defaults.exclude(&"deploy/api.Dockerfile".to_string()));
+        assert!(!defaults.exclude(&"src/model.rs".to_string()));
+    }
 
     // TODO: reactivate test
     fn test_filtering() {
@@ -298,19 +1363,11 @@ mod tests {
             branch: "main".to_string(),
             since: Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap(),
             until: Utc.with_ymd_and_hms(2020, 12, 31, 23, 59, 59).unwrap(),
-            binning: DateGrouping::None
+            binning: DateGrouping::None,
+            ..CommitFilteringOpt::default()
         };
         let commits = repo.mine_objects(&filters).expect("cannot mine");
         assert_eq!(77, commits.len());
-
-        let no_bins = &DateGrouping::None;
-        let binning = Repository::sample_commits(commits, no_bins);
-        assert_eq!(77, binning.len());
-
-        let commits = repo.mine_objects(&filters).expect("cannot mine");
-        let monthly_bins = &DateGrouping::Monthly;
-        let binning = Repository::sample_commits(commits, monthly_bins);
-        assert_eq!(12, binning.len());
     }
 
     // TODO: reactivate test
@@ -325,16 +1382,18 @@ mod tests {
                 since: Utc.with_ymd_and_hms(2020, 12, 8, 17, 14, 0).unwrap(),
                 until: Utc.with_ymd_and_hms(2020, 12, 31, 23, 59, 59).unwrap(),
                 binning: DateGrouping::None,
+                ..CommitFilteringOpt::default()
             },
-            file_filters: FileFilteringOpt::accept_all()
+            file_filters: FileFilteringOpt::accept_all(),
+            ..BetterGitOpt::default()
         };
         let objs = repo.mine_objects(&opts.commit_filters).expect("cannot list commits");
-        let diffs = repo.diffs(&objs, &opts.file_filters);
+        let diffs = repo.diffs(&objs, &opts).expect("cannot diff");
         let matched_files = diffs.values().into_iter().map(|d| d.new_files.clone()).flatten().collect::<Vec<Rc<String>>>();
         assert_eq!(46, matched_files.len());
 
-        let cs_only = FileFilteringOpt::include_only(&[".*cs$"]);
-        let diffs = repo.diffs(&objs, &cs_only);
+        let cs_only = BetterGitOpt { file_filters: FileFilteringOpt::include_only(&[".*cs$"]), ..opts };
+        let diffs = repo.diffs(&objs, &cs_only).expect("cannot diff");
         let matched_files = diffs.values().into_iter().map(|d| d.new_files.clone()).flatten().collect::<Vec<Rc<String>>>();
         matched_files.iter().for_each(|f| {
             assert!(f.ends_with(".cs"), "file doesn't end with '.cs': {}", f)
@@ -1,12 +1,83 @@
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
 use std::rc::Rc;
+use std::str::FromStr;
 
+use anyhow::{bail, Result};
 use chrono::{DateTime, Utc};
 use log::debug;
 use ndarray::Array1;
+use serde::Deserialize;
 
 use crate::bettergit::GroupedBetterDiffs;
 use crate::matrix::NamedMatrix;
 
+/// How a file changed in a transaction is weighted by the lines inserted and deleted in it.
+/// Weights apply to the naive model and hotspots; the Bayesian model counts transactions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ChurnWeighting {
+    /// Every change counts as 1.
+    #[default]
+    None,
+    /// The number of changed lines.
+    Raw,
+    /// One plus the natural logarithm of the number of changed lines.
+    Log,
+    /// The number of changed lines, up to the given maximum.
+    Capped(usize),
+}
+
+impl ChurnWeighting {
+    /// The weight of a change, at least 1 so that renames and binary changes are not lost.
+    pub fn weight(&self, churn: usize) -> f64 {
+        let churn = churn.max(1);
+        match self {
+            ChurnWeighting::None => 1.0,
+            ChurnWeighting::Raw => churn as f64,
+            ChurnWeighting::Log => 1.0 + (churn as f64).ln(),
+            ChurnWeighting::Capped(max) => churn.min((*max).max(1)) as f64,
+        }
+    }
+}
+
+impl FromStr for ChurnWeighting {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (kind, arg) = s.split_once(':').unwrap_or((s, ""));
+        match (kind.to_lowercase().as_str(), arg) {
+            ("none", "") => Ok(ChurnWeighting::None),
+            ("raw", "") => Ok(ChurnWeighting::Raw),
+            ("log", "") => Ok(ChurnWeighting::Log),
+            ("capped", max) => match max.parse::<usize>() {
+                Ok(max) if max > 0 => Ok(ChurnWeighting::Capped(max)),
+                _ => bail!("invalid maximum in {}, expected e.g. capped:100", s),
+            },
+            _ => bail!("cannot parse ChurnWeighting from {}, expected none, raw, log or capped:N", s)
+        }
+    }
+}
+
+impl TryFrom<String> for ChurnWeighting {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        ChurnWeighting::from_str(&s)
+    }
+}
+
+impl Display for ChurnWeighting {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChurnWeighting::None => write!(f, "none"),
+            ChurnWeighting::Raw => write!(f, "raw"),
+            ChurnWeighting::Log => write!(f, "log"),
+            ChurnWeighting::Capped(max) => write!(f, "capped:{}", max),
+        }
+    }
+}
+
 pub struct Changes {
     pub freqs: NamedMatrix<Rc<String>, DateTime<Utc>>,
     pub c_freq: Array1<i32>,
@@ -16,6 +87,11 @@ pub struct Changes {
 
 impl Changes {
     pub fn from_diffs(diffs: GroupedBetterDiffs) -> Changes {
+        Changes::from_weighted_diffs(diffs, ChurnWeighting::None)
+    }
+
+    /// Like `from_diffs`, with each change in `freqs` weighted by its churn.
+    pub fn from_weighted_diffs(diffs: GroupedBetterDiffs, weighting: ChurnWeighting) -> Changes {
         let mut rows = diffs.values()
             .map(|d| d.new_files.iter().map(|f| f.clone()))
             .flatten()
@@ -33,25 +109,93 @@ impl Changes {
             Some("files"),
             Some("dates")
         );
-        let n_files = changes.matrix.nrows();
-        let n_vers = changes.matrix.ncols() as f64;
-        let c_freq= Array1::zeros(n_files);
-        let c_prob =  Array1::zeros(n_files);
-        let mut cc = Changes { freqs: changes, c_freq, c_prob, n_vers };
-        cc.calculate_changes(diffs);
+        let mut cc = Changes::empty(changes);
+        cc.calculate_changes(diffs, weighting);
         cc.calculate_c_freq_and_prob();
         cc
     }
 
-    fn calculate_changes(&mut self, diffs: GroupedBetterDiffs) {
+    pub fn from_freqs(freqs: NamedMatrix<Rc<String>, DateTime<Utc>>) -> Changes {
+        let mut cc = Changes::empty(freqs);
+        cc.calculate_c_freq_and_prob();
+        cc
+    }
+
+    fn empty(freqs: NamedMatrix<Rc<String>, DateTime<Utc>>) -> Changes {
+        let n_files = freqs.matrix.nrows();
+        let n_vers = freqs.matrix.ncols() as f64;
+        let c_freq= Array1::zeros(n_files);
+        let c_prob =  Array1::zeros(n_files);
+        Changes { freqs, c_freq, c_prob, n_vers }
+    }
+
+    /// Maps files to groups such as components, and counts a group as changed in a transaction
+    /// if any of its files changed, with the largest weight among them. Files mapped to `None` are dropped.
+    pub fn aggregate<F>(&self, group_of: F) -> Changes
+    where F: Fn(&str) -> Option<String>
+    {
+        let groups = self.freqs.row_names.iter()
+            .map(|f| group_of(f.as_str()).map(Rc::new))
+            .collect::<Vec<Option<Rc<String>>>>();
+        let mut rows = groups.iter().flatten().cloned().collect::<Vec<Rc<String>>>();
+        rows.sort();
+        rows.dedup();
+        let mut freqs = NamedMatrix::new(
+            rows,
+            self.freqs.col_names.clone(),
+            self.freqs.row_dimname.as_deref(),
+            self.freqs.col_dimname.as_deref()
+        );
+        for (i, group) in groups.iter().enumerate() {
+            let r = match group.as_ref().and_then(|g| freqs.index_of_row(g)) {
+                Some(r) => r,
+                None => continue,
+            };
+            for j in 0..self.freqs.col_names.len() {
+                let x = self.freqs.matrix[[i, j]];
+                if x > freqs.matrix[[r, j]] {
+                    freqs.matrix[[r, j]] = x;
+                }
+            }
+        }
+        Changes::from_freqs(freqs)
+    }
+
+    /// Keeps only the transactions whose date satisfies `keep`, and the files changed in them.
+    pub fn select_dates<F>(&self, keep: F) -> Changes
+    where F: Fn(&DateTime<Utc>) -> bool
+    {
+        let cols = self.freqs.col_names.iter()
+            .enumerate()
+            .filter(|(_, d)| keep(d))
+            .map(|(j, _)| j)
+            .collect::<Vec<usize>>();
+        let rows = (0..self.freqs.row_names.len())
+            .filter(|i| cols.iter().any(|j| self.freqs.matrix[[*i, *j]] > 0.0))
+            .collect::<Vec<usize>>();
+        let mut freqs = NamedMatrix::new(
+            rows.iter().map(|i| self.freqs.row_names[*i].clone()).collect(),
+            cols.iter().map(|j| self.freqs.col_names[*j]).collect(),
+            self.freqs.row_dimname.as_deref(),
+            self.freqs.col_dimname.as_deref()
+        );
+        for (r, i) in rows.iter().enumerate() {
+            for (c, j) in cols.iter().enumerate() {
+                freqs.matrix[[r, c]] = self.freqs.matrix[[*i, *j]];
+            }
+        }
+        Changes::from_freqs(freqs)
+    }
+
+    fn calculate_changes(&mut self, diffs: GroupedBetterDiffs, weighting: ChurnWeighting) {
         debug!("Calculating changes");
         for (dates, diffs_in_commit) in diffs {
             let col = self.freqs.index_of_col(&dates);
-            for new_file in diffs_in_commit.new_files {
-                let row = self.freqs.index_of_row(&new_file);
+            for (i, new_file) in diffs_in_commit.new_files.iter().enumerate() {
+                let row = self.freqs.index_of_row(new_file);
                 match (row, col) {
                     (Some(r), Some(c)) => {
-                        self.freqs.matrix[[r, c]] += 1.0
+                        self.freqs.matrix[[r, c]] += weighting.weight(diffs_in_commit.churn(i))
                     }
                     (_, _) => ()
                 }
@@ -59,13 +203,99 @@ impl Changes {
         }
     }
 
+    /// Counts the transactions changing each file, and estimates the probability that a transaction
+    /// changes it from their share of all transactions.
     fn calculate_c_freq_and_prob(&mut self) {
         let n = self.freqs.matrix.nrows();
         for i in 0..n {
-            let r_sum = self.freqs.matrix.row(i).sum();
-            self.c_freq[i] = r_sum as i32;
-            self.c_prob[i] = r_sum / (n as f64);
+            // transactions changing the file, whatever the weight of the changes
+            let r_count = self.freqs.matrix.row(i).iter().filter(|x| **x > 0.0).count() as f64;
+            self.c_freq[i] = r_count as i32;
+            self.c_prob[i] = if self.n_vers > 0.0 { r_count / self.n_vers } else { 0.0 };
         }
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use bettergit::{diffs_of, diffs_with_churn, GroupedBetterDiffs};
+    use changes::{Changes, ChurnWeighting};
+    use cochanges::{CoChanges, CoChangesOpt};
+    use model::ModelTypes;
+
+    /// Four transactions changing {a, b}, {a, b, c}, {a} and {c}, ten lines per file.
+    fn diffs() -> GroupedBetterDiffs {
+        diffs_with_churn(&[
+            (0, "Alice", "a.rs", 10), (0, "Alice", "b.rs", 10),
+            (1, "Alice", "a.rs", 10), (1, "Alice", "b.rs", 10), (1, "Alice", "c.rs", 10),
+            (2, "Alice", "a.rs", 10), (3, "Alice", "c.rs", 10),
+        ])
+    }
+
+    fn assert_probs<const N: usize>(co_changes: &CoChanges, expected: &[[f64; N]; N]) {
+        for (i, row) in expected.iter().enumerate() {
+            for (j, p) in row.iter().enumerate() {
+                assert!((co_changes.probs.matrix[[i, j]] - p).abs() < 1e-9, "P({} | {})", i, j);
+            }
+        }
+    }
+
+    #[test]
+    fn test_change_probabilities() {
+        let changes = Changes::from_diffs(diffs());
+        assert_eq!(vec!["a.rs", "b.rs", "c.rs"], changes.freqs.row_names.iter().map(|r| r.as_str()).collect::<Vec<_>>());
+        assert_eq!(4.0, changes.n_vers);
+        assert_eq!(vec![3, 2, 2], changes.c_freq.to_vec());
+        assert_eq!(vec![0.75, 0.5, 0.5], changes.c_prob.to_vec());
+
+        let opts = CoChangesOpt { changes_min: 0, freq_min: 0, algorithm: ModelTypes::Bayes };
+        let co_changes = CoChanges::from_changes(&changes, &opts);
+        // a and b change together twice, every other pair once
+        assert_eq!(vec![0.0, 2.0, 1.0], co_changes.freqs.matrix.row(0).to_vec());
+        assert_eq!(vec![1.0, 1.0, 0.0], co_changes.freqs.matrix.row(2).to_vec());
+        // P(i | j) is the share of the transactions changing j that also change i:
+        // both transactions changing b change a, two of the three changing a change b
+        assert_probs(&co_changes, &[[0.0, 1.0, 0.5], [2.0 / 3.0, 0.0, 0.5], [1.0 / 3.0, 0.5, 0.0]]);
+
+        let weighted = Changes::from_weighted_diffs(diffs(), ChurnWeighting::Capped(5));
+        assert_eq!(vec![5.0, 5.0, 5.0, 0.0], weighted.freqs.matrix.row(0).to_vec());
+        assert_eq!(changes.c_prob, weighted.c_prob);
+
+        // the Bayesian model counts transactions, so churn weighting leaves its probabilities unchanged
+        let mixed = || diffs_with_churn(&[
+            (0, "Alice", "a.rs", 100), (0, "Alice", "b.rs", 1), (1, "Alice", "a.rs", 3),
+            (1, "Alice", "b.rs", 40), (2, "Alice", "b.rs", 7), (3, "Alice", "c.rs", 2),
+        ]);
+        for weighting in [ChurnWeighting::Raw, ChurnWeighting::Log, ChurnWeighting::Capped(5)] {
+            let weighted = Changes::from_weighted_diffs(mixed(), weighting);
+            let co_changes = CoChanges::from_changes(&weighted, &opts);
+            assert!(co_changes.probs.matrix.iter().all(|p| (0.0..=1.0).contains(p)), "{}", weighting);
+            assert_eq!(1.0, co_changes.probs.matrix[[1, 0]], "{}", weighting);
+            assert_eq!(2.0, co_changes.freqs.matrix[[0, 1]], "{}", weighting);
+        }
+
+        // a changes once only, so only b and c are left, changing together in two of three transactions
+        let changes = Changes::from_diffs(diffs_of(&[
+            (0, "Alice", "a.rs"), (0, "Alice", "b.rs"), (0, "Alice", "c.rs"),
+            (1, "Alice", "b.rs"), (1, "Alice", "c.rs"), (2, "Alice", "c.rs"),
+        ]));
+        let opts = CoChangesOpt { changes_min: 2, ..opts };
+        let co_changes = CoChanges::from_changes(&changes, &opts);
+        assert_eq!(vec!["b.rs", "c.rs"], co_changes.freqs.row_names.iter().map(|r| r.as_str()).collect::<Vec<_>>());
+        assert_eq!(2.0, co_changes.freqs.matrix[[0, 1]]);
+        assert_probs(&co_changes, &[[0.0, 2.0 / 3.0], [1.0, 0.0]]);
+    }
+
+    #[test]
+    fn test_churn_weighting() {
+        assert_eq!(1.0, ChurnWeighting::None.weight(120));
+        assert_eq!(120.0, ChurnWeighting::Raw.weight(120));
+        assert_eq!(1.0, ChurnWeighting::Raw.weight(0));
+        assert_eq!(1.0, ChurnWeighting::Log.weight(1));
+        assert!((ChurnWeighting::Log.weight(100) - 5.605).abs() < 1e-3);
+        assert_eq!(50.0, ChurnWeighting::Capped(50).weight(120));
+        assert_eq!(ChurnWeighting::Capped(50), "capped:50".parse().unwrap());
+        assert!("capped".parse::<ChurnWeighting>().is_err());
+    }
+}
@@ -1,6 +1,17 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::rc::Rc;
 
+use anyhow::{bail, Result};
+use bincode;
+use itertools::Itertools;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use log::debug;
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
 
 use changes::Changes;
 use matrix::NamedMatrix;
@@ -9,16 +20,42 @@ use crate::model::ModelTypes;
 
 pub type CCMatrix = NamedMatrix<Rc<String>, Rc<String>>;
 
-#[derive(Clone, Debug)]
+const MODEL_MAGIC: &[u8; 4] = b"CCAN";
+const MODEL_VERSION: u16 = 2;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CoChangesOpt {
     pub changes_min: u32,
     pub freq_min: u32,
     pub algorithm: ModelTypes,
 }
 
+impl Default for CoChangesOpt {
+    fn default() -> Self {
+        CoChangesOpt {
+            changes_min: 5,
+            freq_min: 5,
+            algorithm: ModelTypes::Naive,
+        }
+    }
+}
+
 pub struct CoChanges {
     pub freqs: CCMatrix,
     pub probs: CCMatrix,
+    pub opts: CoChangesOpt,
+    /// The component each mined file was aggregated into, empty when files were not aggregated.
+    pub components: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ModelFile {
+    names: Vec<String>,
+    freqs: Array2<f64>,
+    probs: Array2<f64>,
+    opts: CoChangesOpt,
+    components: BTreeMap<String, String>,
 }
 
 pub trait CCFreqsCalculator {
@@ -46,6 +83,163 @@ impl CoChanges {
         CoChanges {
             freqs: cc_freqs,
             probs: cc_probs,
+            opts: opts.clone(),
+            components: BTreeMap::new(),
+        }
+    }
+
+    /// The component a file was aggregated into, or the file itself when files were not aggregated.
+    pub fn component_of(&self, file: &str) -> Option<String> {
+        if self.components.is_empty() {
+            Some(file.to_string())
+        } else {
+            self.components.get(file).cloned()
+        }
+    }
+
+    /// Maps changed files to the components the model was analysed with.
+    pub fn map_files(&self, files: &[String]) -> Vec<String> {
+        files.iter().filter_map(|f| self.component_of(f)).unique().collect()
+    }
+
+    /// Aggregates changes of files into the components the model was analysed with.
+    pub fn map_changes(&self, changes: Changes) -> Changes {
+        if self.components.is_empty() {
+            changes
+        } else {
+            changes.aggregate(|f| self.component_of(f))
+        }
+    }
+
+    /// Pairs of files `(i, j)`, with `i < j`, changing together with a positive probability of at least
+    /// `min_probability`. The probability of a pair is the larger of the two co-change probabilities.
+    pub fn coupled_pairs<'a>(&'a self, min_probability: f64) -> impl Iterator<Item = (usize, usize, f64)> + 'a {
+        let probs = &self.probs;
+        let n = probs.row_names.len();
+        (0..n)
+            .flat_map(move |i| ((i + 1)..n).map(move |j| (i, j, probs.matrix[[i, j]].max(probs.matrix[[j, i]]))))
+            .filter(move |(_, _, p)| *p > 0.0 && *p >= min_probability)
+    }
+
+    /// Writes the model as the `CCAN` magic bytes, the format version and the gzip-compressed
+    /// bincode encoding of names, matrices, options and components.
+    pub fn save<W: Write>(&self, mut w: W) -> Result<()> {
+        w.write_all(MODEL_MAGIC)?;
+        w.write_all(&MODEL_VERSION.to_le_bytes())?;
+        let model = ModelFile {
+            names: self.freqs.row_names.iter().map(|n| n.to_string()).collect(),
+            freqs: self.freqs.matrix.clone(),
+            probs: self.probs.matrix.clone(),
+            opts: self.opts.clone(),
+            components: self.components.clone(),
+        };
+        let mut encoder = GzEncoder::new(w, Compression::default());
+        bincode::serialize_into(&mut encoder, &model)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    pub fn load<R: Read>(mut r: R) -> Result<CoChanges> {
+        let mut magic = [0u8; 4];
+        let mut version = [0u8; 2];
+        if r.read_exact(&mut magic).is_err() || &magic != MODEL_MAGIC {
+            bail!("not a ccan model file")
+        }
+        r.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version != MODEL_VERSION {
+            bail!("unsupported model format version {} (expected {})", version, MODEL_VERSION)
+        }
+        let model: ModelFile = bincode::deserialize_from(GzDecoder::new(r))?;
+        let n = model.names.len();
+        if model.freqs.dim() != (n, n) || model.probs.dim() != (n, n) {
+            bail!("corrupted model: {} files but matrices of shape {:?} and {:?}", n, model.freqs.dim(), model.probs.dim())
         }
+        let names = model.names.into_iter().map(Rc::new).collect::<Vec<Rc<String>>>();
+        let mut freqs = CCMatrix::new(names.clone(), names.clone(), Some("impacted"), Some("changed"));
+        let mut probs = CCMatrix::new(names.clone(), names, Some("impacted"), Some("changing"));
+        freqs.matrix = model.freqs;
+        probs.matrix = model.probs;
+        Ok(CoChanges { freqs, probs, opts: model.opts, components: model.components })
+    }
+
+    pub fn save_file(&self, path: &str) -> Result<()> {
+        self.save(BufWriter::new(File::create(path)?))
+    }
+
+    /// Loads a model to predict with the given algorithm, failing if it was built with another one.
+    pub fn load_file(path: &str, algorithm: Option<ModelTypes>) -> Result<CoChanges> {
+        let cc = match CoChanges::load(BufReader::new(File::open(path)?)) {
+            Ok(cc) => cc,
+            Err(e) => bail!("cannot load model {}: {}", path, e),
+        };
+        match algorithm {
+            Some(a) if a != cc.opts.algorithm => bail!(
+                "model {} was built with the '{}' algorithm and cannot be used to predict with '{}'",
+                path,
+                cc.opts.algorithm,
+                a
+            ),
+            _ => Ok(cc),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use cochanges::{CCMatrix, CoChanges, CoChangesOpt};
+    use model::ModelTypes;
+
+    fn model() -> CoChanges {
+        let names = vec![Rc::new("a.rs".to_string()), Rc::new("b.rs".to_string())];
+        let mut freqs = CCMatrix::new(names.clone(), names.clone(), None, None);
+        let mut probs = CCMatrix::new(names.clone(), names, None, None);
+        freqs.matrix[[0, 1]] = 3.0;
+        probs.matrix[[1, 0]] = 0.75;
+        let opts = CoChangesOpt { changes_min: 1, freq_min: 2, algorithm: ModelTypes::Bayes };
+        let components = [("src/a.rs", "a.rs"), ("src/b.rs", "b.rs"), ("lib/b.rs", "b.rs")].iter()
+            .map(|(f, c)| (f.to_string(), c.to_string()))
+            .collect();
+        CoChanges { freqs, probs, opts, components }
+    }
+
+    #[test]
+    fn test_save_load_roundtrip() {
+        let mut buffer = Vec::new();
+        model().save(&mut buffer).unwrap();
+        let loaded = CoChanges::load(buffer.as_slice()).unwrap();
+        assert_eq!(model().freqs.matrix, loaded.freqs.matrix);
+        assert_eq!(model().probs.matrix, loaded.probs.matrix);
+        assert_eq!(Some(1), loaded.probs.index_of_row(&Rc::new("b.rs".to_string())));
+        assert_eq!(ModelTypes::Bayes, loaded.opts.algorithm);
+        assert_eq!(model().components, loaded.components);
+    }
+
+    #[test]
+    fn test_map_to_components() {
+        let cc = model();
+        let files = ["src/b.rs", "lib/b.rs", "README.md"].map(String::from);
+        assert_eq!(vec!["b.rs"], cc.map_files(&files));
+        let unmapped = CoChanges { components: Default::default(), ..model() };
+        assert_eq!(files.to_vec(), unmapped.map_files(&files));
+    }
+
+    #[test]
+    fn test_coupled_pairs() {
+        let cc = model();
+        assert_eq!(vec![(0, 1, 0.75)], cc.coupled_pairs(0.5).collect::<Vec<_>>());
+        assert!(cc.coupled_pairs(0.8).next().is_none());
+    }
+
+    #[test]
+    fn test_load_rejects_other_versions() {
+        let mut buffer = Vec::new();
+        model().save(&mut buffer).unwrap();
+        buffer[4] = 99;
+        let err = CoChanges::load(buffer.as_slice()).err().unwrap();
+        assert!(err.to_string().contains("version 99"));
+        assert!(CoChanges::load(&b"not a model"[..]).is_err());
     }
 }
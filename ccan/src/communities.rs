@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use anyhow::bail;
+use itertools::Itertools;
+use log::info;
+use serde::Deserialize;
+
+use cochanges::CoChanges;
+use graph::EdgeWeight;
+use ownership::directories_of;
+
+const DISPLAYED_COMMUNITIES: usize = 10;
+const MAX_PROPAGATIONS: usize = 100;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommunityAlgorithm {
+    /// Greedy modularity optimisation, merging the communities found at each level.
+    #[default]
+    Louvain,
+    /// Each file takes the community most strongly tied to it until none changes.
+    Propagation,
+}
+
+impl FromStr for CommunityAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "louvain" => Ok(CommunityAlgorithm::Louvain),
+            "propagation" | "lpa" => Ok(CommunityAlgorithm::Propagation),
+            _ => bail!("cannot parse CommunityAlgorithm from {}", s)
+        }
+    }
+}
+
+impl Display for CommunityAlgorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            CommunityAlgorithm::Louvain => "louvain",
+            CommunityAlgorithm::Propagation => "propagation",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct CommunityOpt {
+    pub enabled: bool,
+    pub algorithm: CommunityAlgorithm,
+    /// Co-change matrix whose symmetrised values weigh the edges.
+    pub weight: EdgeWeight,
+    /// Edges lighter than this are ignored.
+    pub min_weight: f64,
+}
+
+impl Default for CommunityOpt {
+    fn default() -> Self {
+        CommunityOpt {
+            enabled: false,
+            algorithm: CommunityAlgorithm::Louvain,
+            weight: EdgeWeight::Probs,
+            min_weight: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Community {
+    pub id: usize,
+    pub files: Vec<String>,
+    /// Weight of the edges between its files.
+    pub internal_weight: f64,
+    /// Weight of the edges leaving it.
+    pub cut_weight: f64,
+    /// Cut weight over the smaller of its volume and the volume of the rest of the graph, lower is better.
+    pub conductance: f64,
+    /// The directory containing most of its files.
+    pub directory: String,
+    /// Share of its files in `directory`.
+    pub purity: f64,
+}
+
+/// Groups of files changing together, largest first.
+pub struct Communities {
+    pub algorithm: CommunityAlgorithm,
+    pub communities: Vec<Community>,
+    /// Community of each file, in the order of the co-change matrix.
+    pub membership: Vec<(String, usize)>,
+    pub modularity: f64,
+    /// Normalised mutual information between the communities and the directories of the files,
+    /// 1 when they match exactly.
+    pub directory_agreement: f64,
+}
+
+impl Communities {
+    pub fn from_cochanges(cc: &CoChanges, opt: &CommunityOpt) -> Communities {
+        let matrix = match opt.weight {
+            EdgeWeight::Freqs => &cc.freqs,
+            EdgeWeight::Probs => &cc.probs,
+        };
+        let n = matrix.row_names.len();
+        let mut adjacency = vec![Vec::new(); n];
+        for i in 0..n {
+            for j in (i + 1)..n {
+                let w = (matrix.matrix[[i, j]] + matrix.matrix[[j, i]]) / 2.0;
+                if w > 0.0 && w >= opt.min_weight {
+                    adjacency[i].push((j, w));
+                    adjacency[j].push((i, w));
+                }
+            }
+        }
+        let names = matrix.row_names.iter().map(|f| f.to_string()).collect::<Vec<String>>();
+        let labels = match opt.algorithm {
+            CommunityAlgorithm::Louvain => louvain(&adjacency),
+            CommunityAlgorithm::Propagation => propagate_labels(&adjacency),
+        };
+        let communities = Communities::from_labels(opt.algorithm, &names, &adjacency, &labels);
+        info!(
+            "Found {} communities of co-changing files with modularity {:.3}",
+            communities.communities.iter().filter(|c| c.files.len() > 1).count(), communities.modularity
+        );
+        communities
+    }
+
+    fn from_labels(algorithm: CommunityAlgorithm, names: &[String], adjacency: &[Vec<(usize, f64)>], labels: &[usize]) -> Communities {
+        // number communities by decreasing size, then by their first file
+        let groups = (0..names.len())
+            .into_group_map_by(|i| labels[*i])
+            .into_values()
+            .sorted_by(|x, y| y.len().cmp(&x.len()).then(names[x[0]].cmp(&names[y[0]])))
+            .collect::<Vec<Vec<usize>>>();
+        let mut community_of = vec![0; names.len()];
+        for (c, members) in groups.iter().enumerate() {
+            for i in members {
+                community_of[*i] = c;
+            }
+        }
+        let degree = adjacency.iter()
+            .map(|edges| edges.iter().fold(0.0, |d, (_, w)| d + w))
+            .collect::<Vec<f64>>();
+        let total_volume = degree.iter().fold(0.0, |v, d| v + d);
+        let directories = names.iter()
+            .map(|f| directories_of(f).remove(0))
+            .collect::<Vec<String>>();
+        let communities = groups.iter()
+            .enumerate()
+            .map(|(id, members)| {
+                let (mut internal_weight, mut cut_weight) = (0.0, 0.0);
+                for i in members {
+                    for (j, w) in adjacency[*i].iter() {
+                        if community_of[*j] != id {
+                            cut_weight += w;
+                        } else if *i < *j {
+                            internal_weight += w;
+                        }
+                    }
+                }
+                let volume = 2.0 * internal_weight + cut_weight;
+                let smaller = volume.min(total_volume - volume);
+                let (directory, count) = members.iter()
+                    .map(|i| &directories[*i])
+                    .counts()
+                    .into_iter()
+                    .max_by(|x, y| x.1.cmp(&y.1).then(y.0.cmp(x.0)))
+                    .unwrap();
+                Community {
+                    id,
+                    files: members.iter().map(|i| names[*i].clone()).collect(),
+                    internal_weight,
+                    cut_weight,
+                    conductance: if smaller > 0.0 { cut_weight / smaller } else { 0.0 },
+                    directory: directory.clone(),
+                    purity: count as f64 / members.len() as f64,
+                }
+            })
+            .collect();
+        Communities {
+            algorithm,
+            communities,
+            membership: names.iter().cloned().zip(community_of.iter().cloned()).collect(),
+            modularity: modularity(adjacency, &community_of),
+            directory_agreement: mutual_information(&community_of, &directories),
+        }
+    }
+
+    /// Files whose directory differs from the main directory of their community, if it has other files.
+    pub fn misplaced(&self) -> Vec<(&str, &Community)> {
+        self.membership.iter()
+            .map(|(file, c)| (file.as_str(), &self.communities[*c]))
+            .filter(|(file, c)| c.files.len() > 1 && directories_of(file)[0] != c.directory)
+            .collect()
+    }
+}
+
+fn modularity(adjacency: &[Vec<(usize, f64)>], community_of: &[usize]) -> f64 {
+    let degree = adjacency.iter()
+        .map(|edges| edges.iter().fold(0.0, |d, (_, w)| d + w))
+        .collect::<Vec<f64>>();
+    let m2 = degree.iter().fold(0.0, |v, d| v + d);
+    if m2 <= 0.0 {
+        return 0.0;
+    }
+    let communities = community_of.iter().max().map_or(0, |c| c + 1);
+    let mut internal = vec![0.0; communities];
+    let mut totals = vec![0.0; communities];
+    for (i, edges) in adjacency.iter().enumerate() {
+        totals[community_of[i]] += degree[i];
+        for (j, w) in edges {
+            if community_of[i] == community_of[*j] {
+                internal[community_of[i]] += w;
+            }
+        }
+    }
+    let q = internal.iter().zip(totals.iter())
+        .fold(0.0, |q, (inside, total)| q + inside / m2 - (total / m2).powi(2));
+    // a single community has a modularity of exactly 0, up to rounding
+    if q.abs() < 1e-12 { 0.0 } else { q }
+}
+
+/// Communities of the Louvain method: nodes move to the neighbouring community with the largest modularity
+/// gain until none moves, then each community becomes a node of the next level.
+fn louvain(adjacency: &[Vec<(usize, f64)>]) -> Vec<usize> {
+    let mut labels = (0..adjacency.len()).collect::<Vec<usize>>();
+    // weights between the nodes of the current level, self-loops counting the weight inside a node twice
+    let mut level = adjacency.iter()
+        .map(|edges| edges.iter().cloned().collect::<HashMap<usize, f64>>())
+        .collect::<Vec<HashMap<usize, f64>>>();
+    loop {
+        let community_of = move_nodes(&level);
+        let communities = community_of.iter().max().map_or(0, |c| c + 1);
+        if communities == level.len() {
+            return labels;
+        }
+        for label in labels.iter_mut() {
+            *label = community_of[*label];
+        }
+        let mut next = vec![HashMap::<usize, f64>::new(); communities];
+        for (i, edges) in level.iter().enumerate() {
+            for (j, w) in edges {
+                *next[community_of[i]].entry(community_of[*j]).or_default() += w;
+            }
+        }
+        level = next;
+    }
+}
+
+/// One level of Louvain, with the communities numbered from 0 in order of appearance.
+fn move_nodes(level: &[HashMap<usize, f64>]) -> Vec<usize> {
+    let degree = level.iter()
+        .map(|edges| edges.values().fold(0.0, |d, w| d + w))
+        .collect::<Vec<f64>>();
+    let m2 = degree.iter().fold(0.0, |v, d| v + d);
+    let mut community_of = (0..level.len()).collect::<Vec<usize>>();
+    let mut totals = degree.clone();
+    let mut moved = m2 > 0.0;
+    while moved {
+        moved = false;
+        for i in 0..level.len() {
+            let current = community_of[i];
+            totals[current] -= degree[i];
+            let mut links = HashMap::<usize, f64>::new();
+            for (j, w) in level[i].iter().filter(|(j, _)| **j != i) {
+                *links.entry(community_of[*j]).or_default() += w;
+            }
+            let gain = |c: usize, link: f64| link - totals[c] * degree[i] / m2;
+            let mut best = (current, gain(current, links.get(&current).cloned().unwrap_or(0.0)));
+            for (c, link) in links.iter().sorted_by_key(|(c, _)| **c) {
+                let g = gain(*c, *link);
+                if g > best.1 + 1e-12 {
+                    best = (*c, g);
+                }
+            }
+            totals[best.0] += degree[i];
+            if best.0 != current {
+                community_of[i] = best.0;
+                moved = true;
+            }
+        }
+    }
+    renumber(&community_of)
+}
+
+/// Label propagation: each node takes the label with the largest weight among its neighbours,
+/// keeping its own on ties, until no label changes.
+fn propagate_labels(adjacency: &[Vec<(usize, f64)>]) -> Vec<usize> {
+    let mut labels = (0..adjacency.len()).collect::<Vec<usize>>();
+    for _ in 0..MAX_PROPAGATIONS {
+        let mut changed = false;
+        for (i, edges) in adjacency.iter().enumerate() {
+            let mut weights = HashMap::<usize, f64>::new();
+            for (j, w) in edges {
+                *weights.entry(labels[*j]).or_default() += w;
+            }
+            let best = weights.iter()
+                .max_by(|x, y| x.1.total_cmp(y.1).then(y.0.cmp(x.0)))
+                .map(|(label, w)| (*label, *w));
+            if let Some((label, w)) = best {
+                if label != labels[i] && weights.get(&labels[i]).is_none_or(|own| *own < w) {
+                    labels[i] = label;
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    renumber(&labels)
+}
+
+fn renumber(labels: &[usize]) -> Vec<usize> {
+    let mut numbers = HashMap::<usize, usize>::new();
+    labels.iter()
+        .map(|l| {
+            let next = numbers.len();
+            *numbers.entry(*l).or_insert(next)
+        })
+        .collect()
+}
+
+fn mutual_information(communities: &[usize], directories: &[String]) -> f64 {
+    let n = communities.len() as f64;
+    let entropy = |counts: Vec<usize>| counts.iter()
+        .map(|c| *c as f64 / n)
+        .fold(0.0, |h, p| h - p * p.ln());
+    let h_communities = entropy(communities.iter().counts().into_values().collect());
+    let h_directories = entropy(directories.iter().counts().into_values().collect());
+    if h_communities + h_directories <= 0.0 {
+        return 1.0;
+    }
+    let community_counts = communities.iter().counts();
+    let directory_counts = directories.iter().counts();
+    let information = communities.iter().zip(directories.iter())
+        .counts()
+        .into_iter()
+        .fold(0.0, |i, ((c, d), joint)| {
+            let p = joint as f64 / n;
+            i + p * (p * n * n / (community_counts[c] * directory_counts[d]) as f64).ln()
+        });
+    2.0 * information / (h_communities + h_directories)
+}
+
+impl Display for Communities {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let clustered = self.communities.iter().filter(|c| c.files.len() > 1).collect::<Vec<&Community>>();
+        writeln!(
+            f, "Communities by {}: {} with more than one file, modularity {:.3}, agreement with directories {:.3}",
+            self.algorithm, clustered.len(), self.modularity, self.directory_agreement
+        )?;
+        if !clustered.is_empty() {
+            writeln!(f, "  Id   Files   Conductance   Purity   Directory")?;
+            for c in clustered.iter().take(DISPLAYED_COMMUNITIES) {
+                writeln!(f, "{:>4}   {:>5}   {:>11.2}   {:>6.2}   {}", c.id, c.files.len(), c.conductance, c.purity, c.directory)?;
+            }
+        }
+        let misplaced = self.misplaced();
+        if !misplaced.is_empty() {
+            writeln!(f, "Files changing with another directory ({})", misplaced.len())?;
+            for (file, c) in misplaced.iter().take(DISPLAYED_COMMUNITIES) {
+                writeln!(f, "          {} -> community {} ({})", file, c.id, c.directory)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use cochanges::{CCMatrix, CoChanges, CoChangesOpt};
+    use communities::{Communities, CommunityAlgorithm, CommunityOpt};
+
+    #[test]
+    fn test_communities() {
+        // two triangles of files changing together, joined by a weak edge, and a file changing alone
+        let names = ["api/a.rs", "api/b.rs", "db/c.rs", "db/d.rs", "db/e.rs", "api/f.rs", "g.rs"]
+            .iter()
+            .map(|f| Rc::new(f.to_string()))
+            .collect::<Vec<_>>();
+        let mut probs = CCMatrix::new(names.clone(), names.clone(), Some("impacted"), Some("changing"));
+        for (i, j, p) in [(0, 1, 0.9), (0, 5, 0.8), (1, 5, 0.7), (2, 3, 0.9), (2, 4, 0.8), (3, 4, 0.9), (5, 2, 0.1)] {
+            probs.matrix[[i, j]] = p;
+            probs.matrix[[j, i]] = p;
+        }
+        let freqs = CCMatrix::new(names.clone(), names, Some("impacted"), Some("changed"));
+        let cc = CoChanges { freqs, probs, opts: CoChangesOpt::default(), components: Default::default() };
+
+        for algorithm in [CommunityAlgorithm::Louvain, CommunityAlgorithm::Propagation] {
+            let opt = CommunityOpt { algorithm, ..CommunityOpt::default() };
+            let communities = Communities::from_cochanges(&cc, &opt);
+            let membership = communities.membership.iter().map(|(_, c)| *c).collect::<Vec<_>>();
+            assert_eq!(vec![0, 0, 1, 1, 1, 0, 2], membership, "{}", algorithm);
+            assert_eq!(3, communities.communities.len());
+            assert_eq!("api", communities.communities[0].directory);
+            assert_eq!(1.0, communities.communities[0].purity);
+            assert!((communities.communities[0].cut_weight - 0.1).abs() < 1e-9);
+            assert!(communities.modularity > 0.4);
+            assert_eq!(1.0, communities.directory_agreement);
+            assert!(communities.misplaced().is_empty());
+        }
+
+        let mut probs = cc.probs;
+        probs.matrix[[4, 0]] = 2.0;
+        probs.matrix[[0, 4]] = 2.0;
+        let cc = CoChanges { freqs: cc.freqs, probs, opts: CoChangesOpt::default(), components: Default::default() };
+        let communities = Communities::from_cochanges(&cc, &CommunityOpt::default());
+        let misplaced = communities.misplaced().iter().map(|(f, _)| *f).collect::<Vec<_>>();
+        assert_eq!(vec!["db/e.rs"], misplaced);
+        assert!(communities.directory_agreement < 1.0);
+    }
+}
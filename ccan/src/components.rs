@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use git2::{ObjectType, Repository, TreeWalkMode, TreeWalkResult};
+use log::{debug, info};
+use regex::Regex;
+use serde::Deserialize;
+
+use bettergit::glob_to_regex;
+use changes::Changes;
+
+/// How files are aggregated into components before computing co-changes.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ComponentGrouping {
+    #[default]
+    Files,
+    /// The first N directories of each path.
+    Directories(usize),
+    /// A file with one `glob component` pair per line, the first matching glob wins.
+    Mapping(String),
+    /// The Cargo, Maven and npm packages found at the head of the mined branch.
+    Packages,
+}
+
+impl FromStr for ComponentGrouping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (kind, arg) = s.split_once(':').unwrap_or((s, ""));
+        match (kind.to_lowercase().as_str(), arg) {
+            ("files", "") => Ok(ComponentGrouping::Files),
+            ("dirs", depth) | ("directories", depth) => match depth.parse::<usize>() {
+                Ok(depth) if depth > 0 => Ok(ComponentGrouping::Directories(depth)),
+                _ => bail!("invalid directory depth in {}, expected e.g. dirs:2", s),
+            },
+            ("mapping", path) if !path.is_empty() => Ok(ComponentGrouping::Mapping(path.to_string())),
+            ("packages", "") => Ok(ComponentGrouping::Packages),
+            _ => bail!("cannot parse ComponentGrouping from {}, expected files, dirs:N, mapping:FILE or packages", s)
+        }
+    }
+}
+
+impl TryFrom<String> for ComponentGrouping {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        ComponentGrouping::from_str(&s)
+    }
+}
+
+impl Display for ComponentGrouping {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ComponentGrouping::Files => write!(f, "files"),
+            ComponentGrouping::Directories(depth) => write!(f, "dirs:{}", depth),
+            ComponentGrouping::Mapping(path) => write!(f, "mapping:{}", path),
+            ComponentGrouping::Packages => write!(f, "packages"),
+        }
+    }
+}
+
+impl ComponentGrouping {
+    /// The mapper for this grouping, `None` when files are not aggregated.
+    /// Packages are discovered at `revision` of the repository.
+    pub fn mapper(&self, repository: Option<&str>, revision: &str) -> Result<Option<ComponentMapper>> {
+        match self {
+            ComponentGrouping::Files => Ok(None),
+            ComponentGrouping::Directories(depth) => Ok(Some(ComponentMapper::directories(*depth))),
+            ComponentGrouping::Mapping(path) => ComponentMapper::from_mapping_file(path).map(Some),
+            ComponentGrouping::Packages => match repository {
+                Some(repository) => {
+                    let repo = Repository::open(repository)?;
+                    ComponentMapper::from_packages(&repo, revision).map(Some)
+                }
+                None => bail!("grouping files by packages requires a repository, see --repository"),
+            },
+        }
+    }
+}
+
+enum ComponentRules {
+    Depth(usize),
+    Globs(Vec<(Regex, String)>),
+    /// Package directories and names, deepest directories first.
+    Prefixes(Vec<(String, String)>),
+}
+
+pub struct ComponentMapper {
+    rules: ComponentRules,
+}
+
+impl ComponentMapper {
+    pub fn directories(depth: usize) -> ComponentMapper {
+        ComponentMapper { rules: ComponentRules::Depth(depth) }
+    }
+
+    pub fn from_mapping_file(path: &str) -> Result<ComponentMapper> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("cannot read component mapping {}: {}", path, e))?;
+        ComponentMapper::parse_mapping(&content)
+    }
+
+    pub fn parse_mapping(content: &str) -> Result<ComponentMapper> {
+        let mut globs = Vec::new();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(char::is_whitespace) {
+                Some((glob, component)) => {
+                    let regex = Regex::new(&glob_to_regex(glob))
+                        .map_err(|e| anyhow!("invalid glob {}: {}", glob, e))?;
+                    globs.push((regex, component.trim().to_string()));
+                }
+                None => bail!("invalid component mapping line '{}', expected a glob and a component", line),
+            }
+        }
+        Ok(ComponentMapper { rules: ComponentRules::Globs(globs) })
+    }
+
+    /// Finds the `Cargo.toml`, `pom.xml` and `package.json` files at the revision
+    /// and names each package after its manifest, or its directory otherwise.
+    pub fn from_packages(repo: &Repository, revision: &str) -> Result<ComponentMapper> {
+        let tree = repo.revparse_single(revision)?.peel_to_tree()?;
+        let mut manifests = Vec::new();
+        tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+            if dir.split('/').any(|d| d == "node_modules" || d == "target") {
+                return TreeWalkResult::Skip;
+            }
+            let name = entry.name().unwrap_or("");
+            if entry.kind() == Some(ObjectType::Blob) && ["Cargo.toml", "pom.xml", "package.json"].contains(&name) {
+                manifests.push((dir.trim_end_matches('/').to_string(), name.to_string(), entry.id()));
+            }
+            TreeWalkResult::Ok
+        })?;
+        let mut packages = Vec::new();
+        for (dir, manifest, oid) in manifests {
+            let blob = repo.find_blob(oid)?;
+            let content = String::from_utf8_lossy(blob.content());
+            let name = package_name(&manifest, &content).unwrap_or_else(|| {
+                if dir.is_empty() { ".".to_string() } else { dir.clone() }
+            });
+            debug!("Found package {} in {}", name, dir);
+            packages.push((dir, name));
+        }
+        packages.sort_by(|x, y| y.0.len().cmp(&x.0.len()).then(x.0.cmp(&y.0)));
+        packages.dedup_by(|x, y| x.0 == y.0);
+        info!("Found {} packages at {}", packages.len(), revision);
+        Ok(ComponentMapper { rules: ComponentRules::Prefixes(packages) })
+    }
+
+    pub fn component_of(&self, path: &str) -> Option<String> {
+        match &self.rules {
+            ComponentRules::Depth(depth) => {
+                let dirs = path.split('/').collect::<Vec<&str>>();
+                let dirs = &dirs[..dirs.len() - 1];
+                if dirs.is_empty() {
+                    Some(".".to_string())
+                } else {
+                    Some(dirs[..dirs.len().min(*depth)].join("/"))
+                }
+            }
+            ComponentRules::Globs(globs) => globs.iter()
+                .find(|(regex, _)| regex.is_match(path))
+                .map(|(_, component)| component.clone()),
+            ComponentRules::Prefixes(packages) => packages.iter()
+                .find(|(dir, _)| dir.is_empty() || path.starts_with(&format!("{}/", dir)))
+                .map(|(_, name)| name.clone()),
+        }
+    }
+
+    /// The component of each changed file, to map files the same way when predicting.
+    pub fn components(&self, changes: &Changes) -> BTreeMap<String, String> {
+        changes.freqs.row_names.iter()
+            .filter_map(|f| self.component_of(f).map(|c| (f.to_string(), c)))
+            .collect()
+    }
+
+    pub fn aggregate(&self, changes: &Changes) -> Changes {
+        let components = changes.aggregate(|f| self.component_of(f));
+        info!(
+            "Aggregated {} files into {} components",
+            changes.freqs.row_names.len(),
+            components.freqs.row_names.len()
+        );
+        components
+    }
+}
+
+fn package_name(manifest: &str, content: &str) -> Option<String> {
+    let (content, pattern) = match manifest {
+        "Cargo.toml" => (&content[content.find("[package]")?..], r#"(?m)^\s*name\s*=\s*"([^"]+)""#),
+        "package.json" => (content, r#""name"\s*:\s*"([^"]+)""#),
+        _ => {
+            let start = content.find("</parent>").unwrap_or(0);
+            (&content[start..], r"<artifactId>\s*([^<\s]+)\s*</artifactId>")
+        }
+    };
+    Regex::new(pattern).ok()?.captures(content).map(|c| c[1].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    use changes::Changes;
+    use components::{package_name, ComponentGrouping, ComponentMapper};
+    use matrix::NamedMatrix;
+
+    #[test]
+    fn test_component_mappers() {
+        let dirs = ComponentMapper::directories(2);
+        assert_eq!(Some("services/auth".to_string()), dirs.component_of("services/auth/src/main.rs"));
+        assert_eq!(Some("services".to_string()), dirs.component_of("services/Makefile"));
+        assert_eq!(Some(".".to_string()), dirs.component_of("README.md"));
+
+        let mapping = ComponentMapper::parse_mapping("# services\nservices/auth/ auth\n*.ts frontend\nservices/** backend").unwrap();
+        assert_eq!(Some("auth".to_string()), mapping.component_of("services/auth/src/login.ts"));
+        assert_eq!(Some("frontend".to_string()), mapping.component_of("web/app.ts"));
+        assert_eq!(Some("backend".to_string()), mapping.component_of("services/db/main.go"));
+        assert_eq!(None, mapping.component_of("README.md"));
+
+        assert_eq!(Some("ccan".to_string()), package_name("Cargo.toml", "[package]\nname = \"ccan\"\n[dependencies]\nname = \"x\""));
+        assert_eq!(None, package_name("Cargo.toml", "[workspace]\nmembers = []"));
+        assert_eq!(Some("web".to_string()), package_name("package.json", "{\n  \"name\": \"web\",\n  \"version\": \"1\"\n}"));
+        assert_eq!(
+            Some("api".to_string()),
+            package_name("pom.xml", "<parent><artifactId>root</artifactId></parent><artifactId>api</artifactId>")
+        );
+        assert_eq!(ComponentGrouping::Directories(3), "dirs:3".parse().unwrap());
+        assert!("dirs:x".parse::<ComponentGrouping>().is_err());
+    }
+
+    #[test]
+    fn test_aggregate_changes() {
+        let files = ["a/x.rs", "a/y.rs", "b/z.rs", "README.md"];
+        let start = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        let dates = (0..3).map(|d| start + Duration::days(d)).collect::<Vec<_>>();
+        let mut freqs = NamedMatrix::new(files.iter().map(|f| Rc::new(f.to_string())).collect(), dates, Some("files"), Some("dates"));
+        for (i, j) in [(0, 0), (1, 0), (2, 0), (1, 1), (3, 2)] {
+            freqs.matrix[[i, j]] = 1.0;
+        }
+        let mapping = ComponentMapper::parse_mapping("a/ core\nb/ api").unwrap();
+        let changes = Changes::from_freqs(freqs);
+        let components = mapping.aggregate(&changes);
+        assert_eq!(vec!["api", "core"], components.freqs.row_names.iter().map(|r| r.as_str()).collect::<Vec<_>>());
+        assert_eq!(vec![1.0, 0.0, 0.0], components.freqs.matrix.row(0).to_vec());
+        assert_eq!(vec![1.0, 1.0, 0.0], components.freqs.matrix.row(1).to_vec());
+        assert_eq!(Some("core"), mapping.components(&changes).get("a/y.rs").map(String::as_str));
+        assert!(!mapping.components(&changes).contains_key("README.md"));
+    }
+}
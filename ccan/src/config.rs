@@ -0,0 +1,137 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{de, Deserialize, Deserializer};
+use serde_yaml;
+use toml;
+
+use Options;
+
+pub const CONFIG_FILE_NAME: &str = "ccan.toml";
+
+impl Options {
+    /// Reads options from a TOML file, or a YAML one if the extension is `.yaml` or `.yml`.
+    /// Missing values take their default, and the prediction uses the co-change algorithm.
+    pub fn from_file(path: &str) -> Result<Options> {
+        let content = match fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(e) => bail!("cannot read configuration {}: {}", path, e),
+        };
+        let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("");
+        let parsed: Result<Options> = match extension.to_lowercase().as_str() {
+            "yaml" | "yml" => serde_yaml::from_str(&content).map_err(anyhow::Error::from),
+            _ => toml::from_str(&content).map_err(anyhow::Error::from),
+        };
+        let mut opts = match parsed {
+            Ok(opts) => opts,
+            Err(e) => bail!("invalid configuration {}: {}", path, e),
+        };
+        opts.pred_opts.algorithm = opts.cc_opts.algorithm;
+        Ok(opts)
+    }
+
+    /// Path of the configuration file in the root of the repository, if there is one.
+    pub fn find_config(repository: &str) -> Option<String> {
+        let path = Path::new(repository).join(CONFIG_FILE_NAME);
+        if path.is_file() {
+            path.to_str().map(String::from)
+        } else {
+            None
+        }
+    }
+}
+
+fn parse_date(s: &str, h: u32, m: u32, sec: u32) -> std::result::Result<DateTime<Utc>, String> {
+    if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(d.and_hms_opt(h, m, sec).unwrap().and_utc());
+    }
+    s.parse::<DateTime<Utc>>()
+        .map_err(|e| format!("invalid date '{}', expected YYYY-MM-DD: {}", s, e))
+}
+
+/// Deserializes a `YYYY-MM-DD` date (or an RFC 3339 timestamp) at the start of the day.
+pub(crate) fn deserialize_since<'de, D>(d: D) -> std::result::Result<DateTime<Utc>, D::Error>
+where D: Deserializer<'de>
+{
+    let s = String::deserialize(d)?;
+    parse_date(&s, 0, 0, 0).map_err(de::Error::custom)
+}
+
+/// Like `deserialize_since`, for optional dates.
+pub(crate) fn deserialize_optional_since<'de, D>(d: D) -> std::result::Result<Option<DateTime<Utc>>, D::Error>
+where D: Deserializer<'de>
+{
+    deserialize_since(d).map(Some)
+}
+
+/// Deserializes a `YYYY-MM-DD` date (or an RFC 3339 timestamp) at the end of the day.
+pub(crate) fn deserialize_until<'de, D>(d: D) -> std::result::Result<DateTime<Utc>, D::Error>
+where D: Deserializer<'de>
+{
+    let s = String::deserialize(d)?;
+    parse_date(&s, 23, 59, 59).map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use chrono::{TimeZone, Utc};
+
+    use bettergit::DateGrouping;
+    use model::ModelTypes;
+    use Options;
+
+    #[test]
+    fn test_toml_and_yaml_options() {
+        let toml = r#"
+            [git]
+            branch = "develop"
+            since = "2021-02-03"
+            binning = "weekly"
+            include = ['.*\.rs$', '.*\.java$']
+            exclude = ["^target/"]
+
+            [cochanges]
+            algorithm = "bayes"
+            changes_min = 2
+
+            [prediction]
+            until = "2022-01-01"
+        "#;
+        let yaml = r#"
+            git:
+              branch: develop
+              since: "2021-02-03"
+              binning: weekly
+              include: ['.*\.rs$', '.*\.java$']
+              exclude: ["^target/"]
+            cochanges:
+              algorithm: bayes
+              changes_min: 2
+            prediction:
+              until: "2022-01-01"
+        "#;
+        let dir = std::env::temp_dir();
+        for (name, content) in [("ccan-test.toml", toml), ("ccan-test.yaml", yaml)] {
+            let path = dir.join(name);
+            fs::write(&path, content).unwrap();
+            let opts = Options::from_file(path.to_str().unwrap()).unwrap();
+            let git = &opts.git_opts;
+            assert_eq!("develop", git.commit_filters.branch);
+            assert_eq!(Utc.with_ymd_and_hms(2021, 2, 3, 0, 0, 0).unwrap(), git.commit_filters.since);
+            assert!(matches!(git.commit_filters.binning, DateGrouping::Weekly));
+            assert!(git.file_filters.matches(&"src/Main.java".to_string()));
+            assert!(!git.file_filters.matches(&"target/Main.java".to_string()));
+            assert!(!git.file_filters.matches(&"README.md".to_string()));
+            assert_eq!(ModelTypes::Bayes, opts.cc_opts.algorithm);
+            assert_eq!(ModelTypes::Bayes, opts.pred_opts.algorithm);
+            assert_eq!(2, opts.cc_opts.changes_min);
+            assert_eq!(5, opts.cc_opts.freq_min);
+            assert_eq!(Utc.with_ymd_and_hms(2022, 1, 1, 23, 59, 59).unwrap(), opts.pred_opts.until_changes);
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use regex::Regex;
+
+/// Header of a commit message following the conventional commits specification,
+/// e.g. `fix(parser)!: handle empty input`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConventionalCommit {
+    pub kind: String,
+    pub scope: Option<String>,
+    pub breaking: bool,
+    pub description: String,
+}
+
+/// Parses commit headers, compiling the header pattern once for all mined commits.
+pub struct ConventionalParser {
+    header: Regex,
+}
+
+impl Default for ConventionalParser {
+    fn default() -> Self {
+        ConventionalParser::new()
+    }
+}
+
+impl ConventionalParser {
+    pub fn new() -> ConventionalParser {
+        ConventionalParser {
+            header: Regex::new(r"^(?P<kind>[A-Za-z]+)(?:\((?P<scope>[^)]*)\))?(?P<breaking>!)?:\s*(?P<description>.*)$")
+                .unwrap(),
+        }
+    }
+
+    pub fn parse(&self, summary: &str) -> Option<ConventionalCommit> {
+        let captures = self.header.captures(summary.trim())?;
+        Some(ConventionalCommit {
+            kind: captures["kind"].to_lowercase(),
+            scope: captures.name("scope")
+                .map(|s| s.as_str().trim().to_lowercase())
+                .filter(|s| !s.is_empty()),
+            breaking: captures.name("breaking").is_some(),
+            description: captures["description"].to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use conventional::ConventionalParser;
+
+    #[test]
+    fn test_parse_headers() {
+        let parser = ConventionalParser::new();
+        let commit = parser.parse("Fix(Parser)!: handle empty input").unwrap();
+        assert_eq!("fix", commit.kind);
+        assert_eq!(Some("parser".to_string()), commit.scope);
+        assert!(commit.breaking);
+        assert_eq!("handle empty input", commit.description);
+
+        let commit = parser.parse("chore: bump deps").unwrap();
+        assert_eq!(None, commit.scope);
+        assert!(!commit.breaking);
+
+        assert_eq!(None, parser.parse("Merge branch 'main'"));
+        assert_eq!(None, parser.parse("PROJ-12 fix the parser"));
+    }
+}
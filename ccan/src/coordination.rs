@@ -0,0 +1,248 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+use itertools::Itertools;
+use serde::Deserialize;
+
+use bettergit::GroupedBetterDiffs;
+use cochanges::CoChanges;
+use graph::{AttrValue, Edge, Graph, Node};
+use ownership::directories_of;
+
+const DISPLAYED_ENTRIES: usize = 10;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct CoordinationOpt {
+    pub enabled: bool,
+    /// Co-change probability from which two files need their authors to coordinate.
+    pub min_probability: f64,
+}
+
+impl Default for CoordinationOpt {
+    fn default() -> Self {
+        CoordinationOpt {
+            enabled: false,
+            min_probability: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Developer {
+    pub name: String,
+    pub commits: usize,
+    pub files: usize,
+}
+
+/// Two developers changing files that change together.
+#[derive(Clone, Debug)]
+pub struct CoordinationLink {
+    pub developers: (String, String),
+    /// Sum of the co-change probabilities of the pairs of files linking the developers.
+    pub weight: f64,
+    pub pairs: usize,
+}
+
+/// Files changed together often whose authors never changed each other's file.
+#[derive(Clone, Debug)]
+pub struct CoordinationGap {
+    pub files: (String, String),
+    pub authors: (Vec<String>, Vec<String>),
+    /// Co-change probability of the files, see `CoChanges::coupled_pairs`.
+    pub probability: f64,
+}
+
+/// The socio-technical network of the developers, linked through the co-changes of the files they changed.
+pub struct Coordination {
+    pub min_probability: f64,
+    pub developers: Vec<Developer>,
+    /// Strongest links first.
+    pub links: Vec<CoordinationLink>,
+    /// Most probable pairs first.
+    pub gaps: Vec<CoordinationGap>,
+    /// Pairs of files co-changing with at least `min_probability`.
+    pub coupled_pairs: usize,
+    /// Share of the coupled pairs with an author in common, 1 when no pair is coupled.
+    pub congruence: f64,
+}
+
+/// Who changed which files and directories.
+pub struct Authorship {
+    pub developers: Vec<Developer>,
+    /// Authors of each changed file and of each directory containing one.
+    pub authors: HashMap<String, BTreeSet<String>>,
+}
+
+impl Authorship {
+    pub fn from_diffs(diffs: &GroupedBetterDiffs) -> Authorship {
+        let mut authors = HashMap::<String, BTreeSet<String>>::new();
+        let mut commits = HashMap::<String, HashSet<String>>::new();
+        let mut files = HashMap::<String, HashSet<String>>::new();
+        for c in diffs.values().flat_map(|d| d.contributions.iter()) {
+            let author = &c.commit.author;
+            authors.entry(c.file.to_string()).or_default().insert(author.clone());
+            for dir in directories_of(&c.file) {
+                authors.entry(dir).or_default().insert(author.clone());
+            }
+            commits.entry(author.clone()).or_default().insert(c.commit.sha1.clone());
+            files.entry(author.clone()).or_default().insert(c.file.to_string());
+        }
+        let developers = commits.iter()
+            .map(|(name, c)| Developer { name: name.clone(), commits: c.len(), files: files[name].len() })
+            .sorted_by(|x, y| x.name.cmp(&y.name))
+            .collect::<Vec<Developer>>();
+        Authorship { developers, authors }
+    }
+}
+
+impl Coordination {
+    /// Rows of the co-changes that are not changed files, e.g. components, are matched to the authors
+    /// of the directory with the same name.
+    pub fn from_authorship(authorship: Authorship, cc: &CoChanges, opt: &CoordinationOpt) -> Coordination {
+        let Authorship { developers, authors } = authorship;
+        let probs = &cc.probs;
+        let authors_of = |i: usize| authors.get(probs.row_names[i].as_str());
+        let mut links = HashMap::<(String, String), (f64, usize)>::new();
+        let mut gaps = Vec::new();
+        let mut coupled_pairs = 0;
+        for (i, j, probability) in cc.coupled_pairs(0.0) {
+            let (a, b) = match (authors_of(i), authors_of(j)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => continue,
+            };
+            let pairs = a.iter()
+                .cartesian_product(b.iter())
+                .filter(|(x, y)| x != y)
+                .map(|(x, y)| if x < y { (x.clone(), y.clone()) } else { (y.clone(), x.clone()) })
+                .collect::<HashSet<(String, String)>>();
+            for pair in pairs {
+                let link = links.entry(pair).or_default();
+                link.0 += probability;
+                link.1 += 1;
+            }
+            if probability < opt.min_probability {
+                continue;
+            }
+            coupled_pairs += 1;
+            if a.is_disjoint(b) {
+                gaps.push(CoordinationGap {
+                    files: (probs.row_names[i].to_string(), probs.row_names[j].to_string()),
+                    authors: (a.iter().cloned().collect(), b.iter().cloned().collect()),
+                    probability,
+                });
+            }
+        }
+        let links = links.into_iter()
+            .map(|(developers, (weight, pairs))| CoordinationLink { developers, weight, pairs })
+            .sorted_by(|x, y| y.weight.total_cmp(&x.weight).then(x.developers.cmp(&y.developers)))
+            .collect();
+        gaps.sort_by(|x, y| y.probability.total_cmp(&x.probability).then(x.files.cmp(&y.files)));
+        let congruence = if coupled_pairs == 0 { 1.0 } else { 1.0 - gaps.len() as f64 / coupled_pairs as f64 };
+        Coordination { min_probability: opt.min_probability, developers, links, gaps, coupled_pairs, congruence }
+    }
+
+    /// The undirected graph of the developers, weighted by the strength of their links.
+    pub fn to_graph(&self) -> Graph {
+        let mut graph = Graph::new(false);
+        for d in self.developers.iter() {
+            graph.nodes.push(Node {
+                id: format!("n{}", graph.nodes.len()),
+                label: d.name.clone(),
+                cluster: None,
+                attributes: vec![
+                    ("commits".to_string(), AttrValue::Int(d.commits as i64)),
+                    ("files".to_string(), AttrValue::Int(d.files as i64)),
+                ],
+            });
+        }
+        let index_of = |name: &str| self.developers.iter().position(|d| d.name == name);
+        for link in self.links.iter() {
+            if let (Some(source), Some(target)) = (index_of(&link.developers.0), index_of(&link.developers.1)) {
+                graph.edges.push(Edge { source, target, weight: link.weight });
+            }
+        }
+        graph
+    }
+}
+
+impl Display for Coordination {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Coordination between {} developers ({} links)", self.developers.len(), self.links.len())?;
+        writeln!(
+            f, "Socio-technical congruence: {:0.2} ({} of {} pairs co-changing with p >= {} share no author)",
+            self.congruence, self.gaps.len(), self.coupled_pairs, self.min_probability
+        )?;
+        if !self.links.is_empty() {
+            writeln!(f, "    Weight   Pairs   Developers")?;
+            for l in self.links.iter().take(DISPLAYED_ENTRIES) {
+                writeln!(f, "{:>10.2}   {:>5}   {} <-> {}", l.weight, l.pairs, l.developers.0, l.developers.1)?;
+            }
+        }
+        if !self.gaps.is_empty() {
+            writeln!(f, "Coordination gaps")?;
+            for g in self.gaps.iter().take(DISPLAYED_ENTRIES) {
+                writeln!(
+                    f, "          {:0.2}   {} ({}) <-> {} ({})",
+                    g.probability, g.files.0, g.authors.0.join(", "), g.files.1, g.authors.1.join(", ")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use bettergit::diffs_of;
+    use cochanges::{CCMatrix, CoChanges, CoChangesOpt};
+    use coordination::{Authorship, Coordination, CoordinationOpt};
+
+    #[test]
+    fn test_coordination() {
+        let diffs = diffs_of(&[
+            (0, "Alice", "src/api/routes.rs"), (1, "Bob", "src/api/handler.rs"),
+            (2, "Alice", "src/api/handler.rs"), (3, "Carol", "src/db/store.rs"),
+            (4, "Carol", "src/db/store.rs"),
+        ]);
+        let names = ["src/api/handler.rs", "src/api/routes.rs", "src/db/store.rs"]
+            .iter()
+            .map(|f| Rc::new(f.to_string()))
+            .collect::<Vec<_>>();
+        let mut probs = CCMatrix::new(names.clone(), names.clone(), Some("impacted"), Some("changing"));
+        probs.matrix[[0, 1]] = 0.9;
+        probs.matrix[[0, 2]] = 0.2;
+        probs.matrix[[2, 1]] = 0.6;
+        let freqs = CCMatrix::new(names.clone(), names, Some("impacted"), Some("changed"));
+        let cc = CoChanges { freqs, probs, opts: CoChangesOpt::default(), components: Default::default() };
+
+        let coordination = Coordination::from_authorship(Authorship::from_diffs(&diffs), &cc, &CoordinationOpt::default());
+        assert_eq!(vec![2, 1, 2], coordination.developers.iter().map(|d| d.commits).collect::<Vec<_>>());
+        let links = coordination.links.iter()
+            .map(|l| (l.developers.0.as_str(), l.developers.1.as_str(), l.pairs))
+            .collect::<Vec<_>>();
+        // handler and routes link Alice and Bob, store links Carol to both through the other files
+        assert_eq!(vec![("Alice", "Bob", 1), ("Alice", "Carol", 2), ("Bob", "Carol", 1)], links);
+        assert!((coordination.links[1].weight - 0.8).abs() < 1e-9);
+        assert_eq!(2, coordination.coupled_pairs);
+        assert_eq!(1, coordination.gaps.len());
+        assert_eq!(("src/api/routes.rs".to_string(), "src/db/store.rs".to_string()), coordination.gaps[0].files);
+        assert_eq!(0.5, coordination.congruence);
+
+        let graph = coordination.to_graph();
+        assert!(!graph.directed);
+        assert_eq!(3, graph.nodes.len());
+        assert_eq!(3, graph.edges.len());
+
+        let uncoupled = Coordination::from_authorship(Authorship::from_diffs(&diffs), &CoChanges {
+            freqs: CCMatrix::new(Vec::new(), Vec::new(), None, None),
+            probs: CCMatrix::new(Vec::new(), Vec::new(), None, None),
+            opts: CoChangesOpt::default(),
+            components: Default::default(),
+        }, &CoordinationOpt::default());
+        assert!(uncoupled.links.is_empty());
+        assert_eq!(1.0, uncoupled.congruence);
+    }
+}
@@ -0,0 +1,627 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+
+use anyhow::{bail, Result};
+use git2::{ObjectType, Repository, TreeWalkMode, TreeWalkResult};
+use log::{debug, info};
+use regex::Regex;
+use serde::Deserialize;
+
+use cochanges::CoChanges;
+
+const DISPLAYED_DEPENDENCIES: usize = 10;
+/// Larger files are mostly generated code or data and are not scanned.
+const MAX_SOURCE_BYTES: usize = 1 << 20;
+const SCRIPT_EXTENSIONS: [&str; 8] = ["ts", "tsx", "mts", "cts", "js", "jsx", "mjs", "cjs"];
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct DependencyOpt {
+    pub enabled: bool,
+    /// Co-change probability from which a pair without static references is reported.
+    pub min_probability: f64,
+}
+
+impl Default for DependencyOpt {
+    fn default() -> Self {
+        DependencyOpt {
+            enabled: false,
+            min_probability: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ImportLanguage {
+    Rust,
+    Jvm,
+    Python,
+    Script,
+}
+
+impl ImportLanguage {
+    fn of(path: &str) -> Option<ImportLanguage> {
+        let extension = path.rsplit_once('.').map(|(_, e)| e.to_lowercase())?;
+        match extension.as_str() {
+            "rs" => Some(ImportLanguage::Rust),
+            "java" | "kt" | "kts" => Some(ImportLanguage::Jvm),
+            "py" => Some(ImportLanguage::Python),
+            e if SCRIPT_EXTENSIONS.contains(&e) => Some(ImportLanguage::Script),
+            _ => None,
+        }
+    }
+}
+
+/// Finds the files referenced by the imports of a source file, among the files of the repository.
+/// Imports are matched with regular expressions, so references in comments and strings count too,
+/// and imports of external packages are ignored.
+struct ImportScanner<'a> {
+    files: &'a HashSet<String>,
+    /// Files by name, to resolve package paths relative to unknown source roots.
+    by_name: HashMap<&'a str, Vec<&'a str>>,
+    rust_mod: Regex,
+    rust_use: Regex,
+    jvm_import: Regex,
+    python_import: Regex,
+    python_from: Regex,
+    script_imports: Vec<Regex>,
+}
+
+impl<'a> ImportScanner<'a> {
+    fn new(files: &'a HashSet<String>) -> ImportScanner<'a> {
+        let mut by_name = HashMap::<&str, Vec<&str>>::new();
+        for f in files.iter() {
+            by_name.entry(file_name(f)).or_default().push(f.as_str());
+        }
+        ImportScanner {
+            files,
+            by_name,
+            rust_mod: Regex::new(r"(?m)^\s*(?:pub(?:\([^)]*\))?\s+)?mod\s+(\w+)\s*;").unwrap(),
+            rust_use: Regex::new(r"(?m)^\s*(?:pub(?:\([^)]*\))?\s+)?use\s+([^;]+);").unwrap(),
+            jvm_import: Regex::new(r"(?m)^[ \t]*import[ \t]+(?:static[ \t]+)?([\w.]+?)(\.\*)?[ \t]*(?:;|as\b|$)").unwrap(),
+            python_import: Regex::new(r"(?m)^[ \t]*import[ \t]+([\w., \t]+)").unwrap(),
+            python_from: Regex::new(r"(?m)^[ \t]*from[ \t]+(\.*)([\w.]*)[ \t]+import[ \t]+(\([^)]*\)|[^\n#]+)").unwrap(),
+            script_imports: vec![
+                Regex::new(r#"(?:import|export)\s[^;]*?\bfrom\s*['"]([^'"\n]+)['"]"#).unwrap(),
+                Regex::new(r#"\bimport\s*['"]([^'"\n]+)['"]"#).unwrap(),
+                Regex::new(r#"\b(?:require|import)\s*\(\s*['"]([^'"\n]+)['"]\s*\)"#).unwrap(),
+            ],
+        }
+    }
+
+    fn references(&self, path: &str, source: &str) -> HashSet<String> {
+        let references = match ImportLanguage::of(path) {
+            Some(ImportLanguage::Rust) => self.rust_references(path, source),
+            Some(ImportLanguage::Jvm) => self.jvm_references(source),
+            Some(ImportLanguage::Python) => self.python_references(path, source),
+            Some(ImportLanguage::Script) => self.script_references(path, source),
+            None => Vec::new(),
+        };
+        references.into_iter().filter(|r| r != path).collect()
+    }
+
+    fn first_file(&self, candidates: &[String]) -> Option<String> {
+        candidates.iter().find(|c| self.files.contains(*c)).cloned()
+    }
+
+    /// The files whose path is or ends with the relative path.
+    fn files_ending_with(&self, relative: &str) -> Vec<String> {
+        let suffix = format!("/{}", relative);
+        self.by_name.get(file_name(relative))
+            .map(|files| files.iter()
+                .filter(|f| **f == relative || f.ends_with(&suffix))
+                .map(|f| f.to_string())
+                .collect())
+            .unwrap_or_default()
+    }
+
+    fn rust_references(&self, path: &str, source: &str) -> Vec<String> {
+        let module_dir = rust_module_dir(path);
+        let root = self.rust_crate_root(path);
+        let mut references = Vec::new();
+        for c in self.rust_mod.captures_iter(source) {
+            let module = join(&module_dir, &c[1]);
+            references.extend(self.first_file(&[format!("{}.rs", module), format!("{}/mod.rs", module)]));
+        }
+        for c in self.rust_use.captures_iter(source) {
+            for use_path in expand_use_tree(&c[1]) {
+                let segments = use_path.split("::")
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty() && *s != "*")
+                    .collect::<Vec<&str>>();
+                let (base, rest) = match segments.first() {
+                    Some(&"crate") => (root.clone(), &segments[1..]),
+                    Some(&"self") => (module_dir.clone(), &segments[1..]),
+                    Some(&"super") => {
+                        let supers = segments.iter().take_while(|s| **s == "super").count();
+                        let dir = (0..supers).fold(module_dir.clone(), |dir, _| parent(&dir).to_string());
+                        (dir, &segments[supers..])
+                    }
+                    Some(first) => match self.workspace_crate(first) {
+                        Some(other) => (other, &segments[1..]),
+                        // paths are relative to the crate root before the 2018 edition, and may name child modules after;
+                        // anything else is an external crate
+                        None => {
+                            references.extend([&root, &module_dir].iter().find_map(|base| self.rust_module_file(base, &segments)));
+                            continue;
+                        }
+                    },
+                    None => continue,
+                };
+                // items not found in a submodule are defined in the module itself
+                let file = self.rust_module_file(&base, rest).or_else(|| self.first_file(&[
+                    join(&base, "lib.rs"), join(&base, "main.rs"), join(&base, "mod.rs"), format!("{}.rs", base),
+                ]));
+                references.extend(file);
+            }
+        }
+        references
+    }
+
+    /// The file of the longest module prefix of the path.
+    fn rust_module_file(&self, base: &str, segments: &[&str]) -> Option<String> {
+        (1..=segments.len()).rev().find_map(|k| {
+            let module = join(base, &segments[..k].join("/"));
+            self.first_file(&[format!("{}.rs", module), format!("{}/mod.rs", module)])
+        })
+    }
+
+    /// The source directory of another crate of the repository, assuming it is named after its directory.
+    fn workspace_crate(&self, name: &str) -> Option<String> {
+        [name.to_string(), name.replace('_', "-")].iter()
+            .flat_map(|n| self.files_ending_with(&format!("{}/src/lib.rs", n)))
+            .next()
+            .map(|lib| parent(&lib).to_string())
+    }
+
+    /// The directory of the closest `lib.rs` or `main.rs` containing the file.
+    fn rust_crate_root(&self, path: &str) -> String {
+        let mut dir = parent(path);
+        loop {
+            if ["lib.rs", "main.rs"].iter().any(|f| self.files.contains(&join(dir, f))) {
+                return dir.to_string();
+            }
+            if dir.is_empty() {
+                return parent(path).to_string();
+            }
+            dir = parent(dir);
+        }
+    }
+
+    fn jvm_references(&self, source: &str) -> Vec<String> {
+        let mut references = Vec::new();
+        for c in self.jvm_import.captures_iter(source) {
+            let segments = c[1].split('.').collect::<Vec<&str>>();
+            if c.get(2).is_some() {
+                let package = segments.join("/");
+                references.extend(self.files.iter()
+                    .filter(|f| ImportLanguage::of(f) == Some(ImportLanguage::Jvm))
+                    .filter(|f| parent(f) == package || parent(f).ends_with(&format!("/{}", package)))
+                    .cloned());
+                continue;
+            }
+            // static imports and nested classes name members of the class file
+            let found = (1..=segments.len()).rev().find_map(|k| {
+                let class = segments[..k].join("/");
+                let files = ["java", "kt"].iter()
+                    .flat_map(|e| self.files_ending_with(&format!("{}.{}", class, e)))
+                    .collect::<Vec<String>>();
+                if files.is_empty() { None } else { Some(files) }
+            });
+            references.extend(found.unwrap_or_default());
+        }
+        references
+    }
+
+    fn python_references(&self, path: &str, source: &str) -> Vec<String> {
+        let dir = parent(path);
+        let mut references = Vec::new();
+        for c in self.python_import.captures_iter(source) {
+            for module in c[1].split(',') {
+                let module = module.split_whitespace().next().unwrap_or("");
+                references.extend(self.python_module(dir, module));
+            }
+        }
+        for c in self.python_from.captures_iter(source) {
+            let names = c[3].trim_matches(|ch: char| ch == '(' || ch == ')' || ch.is_whitespace())
+                .split(',')
+                .filter_map(|n| n.split_whitespace().next())
+                .collect::<Vec<&str>>();
+            let dots = c[1].len();
+            if dots == 0 {
+                for name in names.iter() {
+                    let submodule = self.python_module(dir, &format!("{}.{}", &c[2], name));
+                    if submodule.is_empty() {
+                        references.extend(self.python_module(dir, &c[2]));
+                    }
+                    references.extend(submodule);
+                }
+                continue;
+            }
+            let base = (1..dots).fold(dir, |d, _| parent(d));
+            let package = match &c[2] {
+                "" => base.to_string(),
+                module => join(base, &module.replace('.', "/")),
+            };
+            for name in names.iter() {
+                let module = join(&package, name);
+                let found = self.first_file(&[format!("{}.py", module), format!("{}/__init__.py", module)])
+                    .or_else(|| self.first_file(&[format!("{}.py", package), format!("{}/__init__.py", package)]));
+                references.extend(found);
+            }
+        }
+        references
+    }
+
+    /// The module next to the importing file, at the root, or under a unique source root.
+    fn python_module(&self, dir: &str, module: &str) -> Vec<String> {
+        if module.is_empty() {
+            return Vec::new();
+        }
+        let relative = module.replace('.', "/");
+        for candidate in [format!("{}.py", relative), format!("{}/__init__.py", relative)] {
+            if let Some(f) = self.first_file(&[join(dir, &candidate), candidate.clone()]) {
+                return vec![f];
+            }
+            let files = self.files_ending_with(&candidate);
+            if files.len() == 1 {
+                return files;
+            }
+        }
+        Vec::new()
+    }
+
+    fn script_references(&self, path: &str, source: &str) -> Vec<String> {
+        let dir = parent(path);
+        let mut references = Vec::new();
+        for regex in self.script_imports.iter() {
+            for c in regex.captures_iter(source) {
+                let specifier = &c[1];
+                if !specifier.starts_with('.') {
+                    continue;
+                }
+                let target = normalize(&join(dir, specifier));
+                let mut candidates = vec![target.clone()];
+                candidates.extend(SCRIPT_EXTENSIONS.iter().map(|e| format!("{}.{}", target, e)));
+                candidates.extend(SCRIPT_EXTENSIONS.iter().map(|e| format!("{}/index.{}", target, e)));
+                // TypeScript imports compiled names, e.g. ./api.js for ./api.ts
+                if let Some((stem, _)) = target.rsplit_once('.').filter(|(_, e)| ["js", "jsx", "mjs", "cjs"].contains(e)) {
+                    candidates.extend(["ts", "tsx", "mts", "cts"].iter().map(|e| format!("{}.{}", stem, e)));
+                }
+                references.extend(self.first_file(&candidates));
+            }
+        }
+        references
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit_once('/').map_or(path, |(_, name)| name)
+}
+
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+fn join(dir: &str, path: &str) -> String {
+    if dir.is_empty() { path.to_string() } else { format!("{}/{}", dir, path) }
+}
+
+fn normalize(path: &str) -> String {
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => { segments.pop(); }
+            s => segments.push(s),
+        }
+    }
+    segments.join("/")
+}
+
+/// The directory of the submodules declared in a Rust file.
+fn rust_module_dir(path: &str) -> String {
+    match file_name(path) {
+        "lib.rs" | "main.rs" | "mod.rs" => parent(path).to_string(),
+        _ => path.trim_end_matches(".rs").to_string(),
+    }
+}
+
+/// The paths of a use tree, e.g. `a::{b, c::{self, d}}` gives `a::b`, `a::c` and `a::c::d`.
+fn expand_use_tree(tree: &str) -> Vec<String> {
+    let tree = tree.trim();
+    let open = match tree.find('{') {
+        Some(open) => open,
+        None => return vec![tree.split(" as ").next().unwrap_or("").trim().to_string()],
+    };
+    let prefix = tree[..open].trim().trim_end_matches("::");
+    let inner = tree[open + 1..].trim_end();
+    let inner = inner.strip_suffix('}').unwrap_or(inner);
+    let mut items = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, ch) in inner.char_indices() {
+        match ch {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&inner[start..]);
+    items.into_iter()
+        .filter(|item| !item.trim().is_empty())
+        .flat_map(expand_use_tree)
+        .map(|path| match (prefix, path.as_str()) {
+            (_, "self") => prefix.to_string(),
+            ("", _) => path,
+            _ => format!("{}::{}", prefix, path),
+        })
+        .collect()
+}
+
+/// The files of a revision and the files their imports refer to.
+pub struct ReferenceGraph {
+    /// Files referenced by each file of the revision.
+    pub references: HashMap<String, HashSet<String>>,
+    /// Files written in a language whose imports are scanned.
+    pub scanned: HashSet<String>,
+}
+
+impl ReferenceGraph {
+    pub fn from_repository(repo: &Repository, revision: &str) -> Result<ReferenceGraph> {
+        let tree = match repo.revparse_single(revision) {
+            Ok(obj) => obj.peel_to_tree()?,
+            Err(e) => bail!("cannot find revision {}: {}", revision, e.message()),
+        };
+        let mut blobs = Vec::new();
+        tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() == Some(ObjectType::Blob) {
+                blobs.push((format!("{}{}", dir, entry.name().unwrap_or("")), entry.id()));
+            }
+            TreeWalkResult::Ok
+        })?;
+        let mut sources = Vec::new();
+        for (path, oid) in blobs.iter() {
+            if ImportLanguage::of(path).is_none() {
+                continue;
+            }
+            let blob = repo.find_blob(*oid)?;
+            if blob.is_binary() || blob.size() > MAX_SOURCE_BYTES {
+                debug!("Not scanning the imports of {}", path);
+                continue;
+            }
+            sources.push((path.clone(), String::from_utf8_lossy(blob.content()).to_string()));
+        }
+        let graph = ReferenceGraph::from_sources(blobs.into_iter().map(|(path, _)| path).collect(), &sources);
+        info!(
+            "Found {} references between the {} files at {}",
+            graph.references.values().map(|r| r.len()).sum::<usize>(), graph.references.len(), revision
+        );
+        Ok(graph)
+    }
+
+    /// Java and Kotlin classes may use the classes of their package without importing them,
+    /// so they also refer to the files of their directory whose name they mention.
+    pub fn from_sources(files: Vec<String>, sources: &[(String, String)]) -> ReferenceGraph {
+        let files = files.into_iter().collect::<HashSet<String>>();
+        let scanner = ImportScanner::new(&files);
+        let mut references = files.iter()
+            .map(|f| (f.clone(), HashSet::new()))
+            .collect::<HashMap<String, HashSet<String>>>();
+        let mut packages = HashMap::<&str, Vec<&str>>::new();
+        for (path, _) in sources.iter().filter(|(p, _)| ImportLanguage::of(p) == Some(ImportLanguage::Jvm)) {
+            packages.entry(parent(path)).or_default().push(path);
+        }
+        for (path, source) in sources.iter() {
+            let mut found = scanner.references(path, source);
+            if let Some(classes) = packages.get(parent(path)) {
+                let words = source.split(|c: char| !c.is_alphanumeric() && c != '_').collect::<HashSet<&str>>();
+                found.extend(classes.iter()
+                    .filter(|c| **c != path && words.contains(file_name(c).split('.').next().unwrap_or("")))
+                    .map(|c| c.to_string()));
+            }
+            references.entry(path.clone()).or_default().extend(found);
+        }
+        ReferenceGraph {
+            references,
+            scanned: sources.iter().map(|(path, _)| path.clone()).collect(),
+        }
+    }
+
+    /// The graph between the groups of files, e.g. components, dropping the files without a group.
+    pub fn aggregate<F>(&self, group_of: F) -> ReferenceGraph
+    where F: Fn(&str) -> Option<String>
+    {
+        let groups = self.references.keys()
+            .filter_map(|f| group_of(f).map(|g| (f.as_str(), g)))
+            .collect::<HashMap<&str, String>>();
+        let mut references = HashMap::<String, HashSet<String>>::new();
+        for (file, group) in groups.iter() {
+            let targets = self.references[*file].iter()
+                .filter_map(|r| groups.get(r.as_str()))
+                .filter(|g| *g != group)
+                .cloned();
+            references.entry(group.clone()).or_default().extend(targets);
+        }
+        let scanned = self.scanned.iter().filter_map(|f| groups.get(f.as_str()).cloned()).collect();
+        ReferenceGraph { references, scanned }
+    }
+
+    /// The node of a file or group, or of the file containing a symbol.
+    fn node_of<'b>(&self, name: &'b str) -> Option<&'b str> {
+        if self.references.contains_key(name) {
+            return Some(name);
+        }
+        name.split_once("::")
+            .map(|(file, _)| file)
+            .filter(|file| self.references.contains_key(*file))
+    }
+
+    fn reachable(&self, from: &str) -> HashSet<String> {
+        let mut reached = HashSet::new();
+        let mut queue = VecDeque::from([from.to_string()]);
+        while let Some(node) = queue.pop_front() {
+            for next in self.references.get(&node).into_iter().flatten() {
+                if reached.insert(next.clone()) {
+                    queue.push_back(next.clone());
+                }
+            }
+        }
+        reached
+    }
+}
+
+/// Files changing together without a chain of imports from one to the other.
+#[derive(Clone, Debug)]
+pub struct HiddenDependency {
+    pub files: (String, String),
+    /// Co-change probability of the files, see `CoChanges::coupled_pairs`.
+    pub probability: f64,
+    /// Whether the imports of each file were scanned, e.g. not for configuration or SQL files.
+    pub scanned: (bool, bool),
+}
+
+pub struct HiddenDependencies {
+    pub min_probability: f64,
+    /// Pairs of files existing at the revision and co-changing with at least `min_probability`.
+    pub coupled_pairs: usize,
+    /// Most probable pairs first.
+    pub hidden: Vec<HiddenDependency>,
+}
+
+impl HiddenDependencies {
+    pub fn detect(cc: &CoChanges, graph: &ReferenceGraph, opt: &DependencyOpt) -> HiddenDependencies {
+        let probs = &cc.probs;
+        let mut reachable = HashMap::<&str, HashSet<String>>::new();
+        let mut hidden = Vec::new();
+        let mut coupled_pairs = 0;
+        for (i, j, probability) in cc.coupled_pairs(opt.min_probability) {
+            let (a, b) = match (graph.node_of(&probs.row_names[i]), graph.node_of(&probs.row_names[j])) {
+                (Some(a), Some(b)) => (a, b),
+                _ => continue,
+            };
+            coupled_pairs += 1;
+            if a == b {
+                continue;
+            }
+            let a_reaches_b = reachable.entry(a).or_insert_with(|| graph.reachable(a)).contains(b);
+            if a_reaches_b || reachable.entry(b).or_insert_with(|| graph.reachable(b)).contains(a) {
+                continue;
+            }
+            hidden.push(HiddenDependency {
+                files: (probs.row_names[i].to_string(), probs.row_names[j].to_string()),
+                probability,
+                scanned: (graph.scanned.contains(a), graph.scanned.contains(b)),
+            });
+        }
+        hidden.sort_by(|x, y| y.probability.total_cmp(&x.probability).then(x.files.cmp(&y.files)));
+        HiddenDependencies { min_probability: opt.min_probability, coupled_pairs, hidden }
+    }
+}
+
+impl Display for HiddenDependencies {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f, "Hidden dependencies: {} of {} pairs co-changing with p >= {} have no static reference path",
+            self.hidden.len(), self.coupled_pairs, self.min_probability
+        )?;
+        for d in self.hidden.iter().take(DISPLAYED_DEPENDENCIES) {
+            let scanned = |s: bool| if s { "" } else { " (not scanned)" };
+            writeln!(
+                f, "          {:0.2}   {}{} <-> {}{}",
+                d.probability, d.files.0, scanned(d.scanned.0), d.files.1, scanned(d.scanned.1)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use cochanges::{CCMatrix, CoChanges, CoChangesOpt};
+    use dependencies::{expand_use_tree, DependencyOpt, HiddenDependencies, ReferenceGraph};
+
+    fn references(graph: &ReferenceGraph, file: &str) -> Vec<String> {
+        let mut references = graph.references[file].iter().cloned().collect::<Vec<_>>();
+        references.sort();
+        references
+    }
+
+    #[test]
+    fn test_import_scanners() {
+        assert_eq!(vec!["a::b", "a::c", "a::c::d", "a::e"], expand_use_tree("a::{b, c::{self, d as x}, e}"));
+        assert_eq!(vec!["a::b::c"], expand_use_tree("a::{b::{c}}"));
+
+        let files = [
+            "core/src/lib.rs", "core/src/model.rs", "cli/src/main.rs", "core/src/db/mod.rs", "core/src/db/store.rs", "core/src/api.rs",
+            "app/src/main/java/com/acme/App.java", "app/src/main/java/com/acme/Config.java",
+            "app/src/main/java/com/acme/util/Strings.kt", "app/src/main/java/com/acme/util/Dates.kt",
+            "py/pkg/__init__.py", "py/pkg/models.py", "py/pkg/views.py", "py/pkg/sub/helpers.py", "py/main.py",
+            "web/src/api.ts", "web/src/app.tsx", "web/src/components/index.ts", "web/schema.sql",
+        ].iter().map(|f| f.to_string()).collect::<Vec<_>>();
+        let sources = [
+            ("core/src/lib.rs", "mod model;\npub mod db;\nmod api;\n"),
+            ("core/src/db/mod.rs", "pub mod store;\nuse crate::model::{Model, Field};\n"),
+            ("core/src/db/store.rs", "use super::super::api;\nuse super::Connection;\nuse std::fmt;\n"),
+            ("core/src/api.rs", "use db::store::Store;\n"),
+            ("cli/src/main.rs", "extern crate core;\nuse core::api::get;\nuse core::Options;\n"),
+            ("app/src/main/java/com/acme/App.java", "package com.acme;\nimport com.acme.util.*;\nimport java.util.List;\nclass App { Config config; }\n"),
+            ("app/src/main/java/com/acme/Config.java", "package com.acme;\nimport static com.acme.util.Strings.trim;\n"),
+            ("app/src/main/java/com/acme/util/Strings.kt", "package com.acme.util\n"),
+            ("py/pkg/views.py", "from . import models\nfrom .sub.helpers import (\n    slug,\n)\nimport os, json\n"),
+            ("py/main.py", "from pkg import views\nimport pkg.models as m\n"),
+            ("web/src/app.tsx", "import { get } from './api.js';\nimport './components';\nconst x = require('react');\n"),
+        ];
+        let sources = sources.iter().map(|(p, s)| (p.to_string(), s.to_string())).collect::<Vec<_>>();
+        let graph = ReferenceGraph::from_sources(files, &sources);
+        assert_eq!(vec!["core/src/api.rs", "core/src/db/mod.rs", "core/src/model.rs"], references(&graph, "core/src/lib.rs"));
+        assert_eq!(vec!["core/src/db/store.rs", "core/src/model.rs"], references(&graph, "core/src/db/mod.rs"));
+        assert_eq!(vec!["core/src/api.rs", "core/src/db/mod.rs"], references(&graph, "core/src/db/store.rs"));
+        assert_eq!(vec!["core/src/db/store.rs"], references(&graph, "core/src/api.rs"));
+        assert_eq!(vec!["core/src/api.rs", "core/src/lib.rs"], references(&graph, "cli/src/main.rs"));
+        assert_eq!(vec![
+            "app/src/main/java/com/acme/Config.java",
+            "app/src/main/java/com/acme/util/Dates.kt",
+            "app/src/main/java/com/acme/util/Strings.kt",
+        ], references(&graph, "app/src/main/java/com/acme/App.java"));
+        assert_eq!(vec!["app/src/main/java/com/acme/util/Strings.kt"], references(&graph, "app/src/main/java/com/acme/Config.java"));
+        assert_eq!(vec!["py/pkg/models.py", "py/pkg/sub/helpers.py"], references(&graph, "py/pkg/views.py"));
+        assert_eq!(vec!["py/pkg/models.py", "py/pkg/views.py"], references(&graph, "py/main.py"));
+        assert_eq!(vec!["web/src/api.ts", "web/src/components/index.ts"], references(&graph, "web/src/app.tsx"));
+        assert!(graph.scanned.contains("web/src/app.tsx"));
+        assert!(!graph.scanned.contains("web/schema.sql"));
+    }
+
+    #[test]
+    fn test_hidden_dependencies() {
+        let files = ["src/a.py", "src/b.py", "src/c.py", "schema.sql", "src/d.py"]
+            .iter().map(|f| f.to_string()).collect::<Vec<_>>();
+        let sources = [("src/a.py", "import b\n"), ("src/b.py", "from . import c\n"), ("src/c.py", ""), ("src/d.py", "")]
+            .iter().map(|(p, s)| (p.to_string(), s.to_string())).collect::<Vec<_>>();
+        let graph = ReferenceGraph::from_sources(files, &sources);
+
+        let names = ["src/a.py", "src/c.py", "schema.sql", "src/d.py::f", "src/deleted.py"]
+            .iter().map(|f| Rc::new(f.to_string())).collect::<Vec<_>>();
+        let mut probs = CCMatrix::new(names.clone(), names.clone(), Some("impacted"), Some("changing"));
+        // a imports c through b, c changes with the schema, and the deleted file changes with everything
+        for (i, j, p) in [(0, 1, 0.9), (1, 2, 0.8), (3, 0, 0.6), (0, 3, 0.3), (4, 0, 1.0), (4, 2, 1.0)] {
+            probs.matrix[[i, j]] = p;
+        }
+        let freqs = CCMatrix::new(names.clone(), names, Some("impacted"), Some("changed"));
+        let cc = CoChanges { freqs, probs, opts: CoChangesOpt::default(), components: Default::default() };
+
+        let hidden = HiddenDependencies::detect(&cc, &graph, &DependencyOpt::default());
+        assert_eq!(3, hidden.coupled_pairs);
+        let pairs = hidden.hidden.iter().map(|d| (d.files.0.as_str(), d.files.1.as_str(), d.scanned)).collect::<Vec<_>>();
+        assert_eq!(vec![("src/c.py", "schema.sql", (true, false)), ("src/a.py", "src/d.py::f", (true, true))], pairs);
+
+        let by_dir = graph.aggregate(|f| Some(f.split('/').next().filter(|_| f.contains('/')).unwrap_or(".").to_string()));
+        assert_eq!(2, by_dir.references.len());
+        assert!(by_dir.references["src"].is_empty());
+    }
+}
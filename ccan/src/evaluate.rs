@@ -0,0 +1,169 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+use log::debug;
+
+use changes::Changes;
+use cochanges::{CoChanges, CoChangesOpt};
+use predict::PredictionOpt;
+
+/// Percentage of the transactions, oldest first, trained on when no split date is given.
+pub const DEFAULT_TRAIN_PERCENT: usize = 80;
+
+#[derive(Clone, Debug)]
+pub struct EvaluationOpt {
+    pub split: DateTime<Utc>,
+    pub top_k: usize,
+}
+
+impl EvaluationOpt {
+    /// The date of the first transaction after the oldest `DEFAULT_TRAIN_PERCENT` of the sorted dates.
+    pub fn default_split(dates: &[DateTime<Utc>]) -> Option<DateTime<Utc>> {
+        dates.get(dates.len() * DEFAULT_TRAIN_PERCENT / 100).cloned()
+    }
+}
+
+/// Result of a backtest: the model is trained on the transactions before the split date, then each
+/// file of a later transaction is used to predict the other files changed with it.
+///
+/// Precision only measures the queries the model answers, i.e. files it has seen changing with others,
+/// while recall and hit rate count the unanswered queries as misses.
+#[derive(Clone, Debug, Default)]
+pub struct Evaluation {
+    pub train_transactions: usize,
+    pub test_transactions: usize,
+    pub queries: usize,
+    pub answered_queries: usize,
+    /// Share of the predicted files that changed with the query, averaged over the answered queries.
+    pub precision: f64,
+    /// Share of the files changed with the query that were predicted, averaged over all queries.
+    pub recall: f64,
+    pub f1: f64,
+    /// Share of all queries with at least one correct prediction.
+    pub hit_rate: f64,
+}
+
+impl Evaluation {
+    pub fn backtest(
+        changes: &Changes,
+        cc_opts: &CoChangesOpt,
+        pred_opts: &PredictionOpt,
+        opt: &EvaluationOpt,
+    ) -> Evaluation {
+        let train = changes.select_dates(|d| d < &opt.split);
+        let test = changes.select_dates(|d| d >= &opt.split);
+        debug!(
+            "Backtesting on {} transactions, trained on {}",
+            test.freqs.col_names.len(),
+            train.freqs.col_names.len()
+        );
+        let cc = CoChanges::from_changes(&train, cc_opts);
+        let model = pred_opts.algorithm.get_model();
+
+        let mut eval = Evaluation {
+            train_transactions: train.freqs.col_names.len(),
+            test_transactions: test.freqs.col_names.len(),
+            ..Evaluation::default()
+        };
+        let (mut precision, mut recall, mut hits) = (0.0, 0.0, 0);
+        for j in 0..test.freqs.col_names.len() {
+            let files = test.freqs.matrix.column(j)
+                .iter()
+                .enumerate()
+                .filter(|(_, x)| **x > 0.0)
+                .map(|(i, _)| test.freqs.row_names[i].to_string())
+                .collect::<Vec<String>>();
+            if files.len() < 2 {
+                continue;
+            }
+            for query in files.iter() {
+                eval.queries += 1;
+                let expected = files.iter()
+                    .filter(|f| *f != query)
+                    .collect::<HashSet<&String>>();
+                let predicted = model.predict(&cc, &vec![query.clone()], pred_opts)
+                    .into_iter()
+                    .filter(|(f, p)| f != query && *p > 0.0)
+                    .sorted_by(|x, y| y.1.total_cmp(&x.1))
+                    .take(opt.top_k)
+                    .collect::<Vec<(String, f64)>>();
+                if predicted.is_empty() {
+                    continue;
+                }
+                eval.answered_queries += 1;
+                let found = predicted.iter().filter(|(f, _)| expected.contains(f)).count();
+                precision += found as f64 / predicted.len() as f64;
+                recall += found as f64 / expected.len() as f64;
+                if found > 0 {
+                    hits += 1;
+                }
+            }
+        }
+        if eval.answered_queries > 0 {
+            eval.precision = precision / eval.answered_queries as f64;
+        }
+        if eval.queries > 0 {
+            eval.recall = recall / eval.queries as f64;
+            eval.hit_rate = hits as f64 / eval.queries as f64;
+        }
+        if eval.precision + eval.recall > 0.0 {
+            eval.f1 = 2.0 * eval.precision * eval.recall / (eval.precision + eval.recall);
+        }
+        eval
+    }
+}
+
+impl Display for Evaluation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Training transactions: {}", self.train_transactions)?;
+        writeln!(f, "Test transactions:     {}", self.test_transactions)?;
+        writeln!(f, "Queries (answered):    {} ({})", self.queries, self.answered_queries)?;
+        writeln!(f, "Precision:             {:0.3}", self.precision)?;
+        writeln!(f, "Recall:                {:0.3}", self.recall)?;
+        writeln!(f, "F1:                    {:0.3}", self.f1)?;
+        writeln!(f, "Hit rate:              {:0.3}", self.hit_rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use bettergit::diffs_of;
+    use changes::Changes;
+    use cochanges::CoChangesOpt;
+    use evaluate::{Evaluation, EvaluationOpt};
+    use model::ModelTypes;
+    use predict::PredictionOpt;
+
+    #[test]
+    fn test_backtest() {
+        // trained on {a, b}, {a, b, c} and {c, d}, tested on {a, b}, {c, e} and {d}
+        let changes = Changes::from_diffs(diffs_of(&[
+            (0, "Alice", "a.rs"), (0, "Alice", "b.rs"),
+            (1, "Alice", "a.rs"), (1, "Alice", "b.rs"), (1, "Alice", "c.rs"),
+            (2, "Alice", "c.rs"), (2, "Alice", "d.rs"),
+            (10, "Alice", "a.rs"), (10, "Alice", "b.rs"),
+            (11, "Alice", "c.rs"), (11, "Alice", "e.rs"),
+            (12, "Alice", "d.rs"),
+        ]));
+        let start = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(Some(start + Duration::days(11)), EvaluationOpt::default_split(&changes.freqs.col_names));
+        assert_eq!(None, EvaluationOpt::default_split(&[]));
+
+        let cc_opts = CoChangesOpt { changes_min: 0, freq_min: 0, algorithm: ModelTypes::Bayes };
+        let pred_opts = PredictionOpt { algorithm: ModelTypes::Bayes, ..PredictionOpt::default() };
+        let opt = EvaluationOpt { split: start + Duration::days(10), top_k: 1 };
+        let eval = Evaluation::backtest(&changes, &cc_opts, &pred_opts, &opt);
+        assert_eq!((3, 3), (eval.train_transactions, eval.test_transactions));
+        // a and b predict each other, c predicts a file it never changed with, e is unknown to the model
+        // and {d} has nothing to predict
+        assert_eq!((4, 3), (eval.queries, eval.answered_queries));
+        assert!((eval.precision - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(0.5, eval.recall);
+        assert_eq!(0.5, eval.hit_rate);
+        assert!((eval.f1 - 4.0 / 7.0).abs() < 1e-9);
+    }
+}
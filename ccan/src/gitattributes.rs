@@ -0,0 +1,186 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use git2::{ObjectType, Oid, Repository, Tree, TreeWalkMode, TreeWalkResult};
+use log::debug;
+use regex::Regex;
+use serde::Deserialize;
+
+use bettergit::glob_to_regex;
+
+pub const ATTRIBUTES_FILE_NAME: &str = ".gitattributes";
+
+const GENERATED: &str = "linguist-generated";
+const VENDORED: &str = "linguist-vendored";
+const DIFF: &str = "diff";
+
+/// The `.gitattributes` files of a tree, by directory, with the ids of their blobs.
+pub type AttributeFiles = Vec<(String, Oid)>;
+
+/// Where to read the `.gitattributes` files that exclude generated, vendored and non-diffable files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttributesScope {
+    #[default]
+    None,
+    Head,
+    Commit,
+}
+
+impl FromStr for AttributesScope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(AttributesScope::None),
+            "head" => Ok(AttributesScope::Head),
+            "commit" => Ok(AttributesScope::Commit),
+            _ => bail!("cannot parse AttributesScope from {}", s)
+        }
+    }
+}
+
+impl Display for AttributesScope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            AttributesScope::None => "none",
+            AttributesScope::Head => "head",
+            AttributesScope::Commit => "commit",
+        };
+        write!(f, "{s}")
+    }
+}
+
+struct AttributeRule {
+    path: Regex,
+    attribute: &'static str,
+    set: bool,
+}
+
+/// Paths marked as `linguist-generated`, `linguist-vendored` or `-diff` (including `binary`).
+/// As in git, the last matching line wins and nested files override their parents.
+/// Unlike git, a pattern ending in `/` such as `dist/` also matches every file under the directory.
+#[derive(Default)]
+pub struct GitAttributes {
+    rules: Vec<AttributeRule>,
+}
+
+impl GitAttributes {
+    pub fn files_in(tree: &Tree) -> Result<AttributeFiles> {
+        let mut files = Vec::new();
+        tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.name() == Some(ATTRIBUTES_FILE_NAME) && entry.kind() == Some(ObjectType::Blob) {
+                files.push((dir.trim_end_matches('/').to_string(), entry.id()));
+            }
+            TreeWalkResult::Ok
+        })?;
+        Ok(files)
+    }
+
+    pub fn from_files(repo: &Repository, files: &AttributeFiles) -> Result<GitAttributes> {
+        let mut attributes = GitAttributes::default();
+        for (dir, oid) in files {
+            let blob = repo.find_blob(*oid)?;
+            attributes.add(dir, &String::from_utf8_lossy(blob.content()));
+        }
+        debug!("Read {} relevant attribute rules", attributes.rules.len());
+        Ok(attributes)
+    }
+
+    pub fn parse(content: &str) -> GitAttributes {
+        let mut attributes = GitAttributes::default();
+        attributes.add("", content);
+        attributes
+    }
+
+    fn add(&mut self, dir: &str, content: &str) {
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut tokens = line.split_whitespace();
+            let pattern = match tokens.next() {
+                Some(p) => p,
+                None => continue,
+            };
+            let glob = if dir.is_empty() {
+                pattern.to_string()
+            } else if pattern.trim_end_matches('/').contains('/') {
+                format!("{}/{}", dir, pattern.trim_start_matches('/'))
+            } else {
+                format!("{}/**/{}", dir, pattern)
+            };
+            let path = match Regex::new(&glob_to_regex(&glob)) {
+                Ok(r) => r,
+                Err(_) => {
+                    debug!("Skipping attribute pattern {}", pattern);
+                    continue;
+                }
+            };
+            for token in tokens {
+                for (attribute, set) in GitAttributes::parse_attribute(token) {
+                    self.rules.push(AttributeRule { path: path.clone(), attribute, set });
+                }
+            }
+        }
+    }
+
+    fn parse_attribute(token: &str) -> Vec<(&'static str, bool)> {
+        let (name, set) = match token.chars().next() {
+            Some('-') | Some('!') => (&token[1..], false),
+            _ => match token.split_once('=') {
+                Some((name, value)) => (name, !matches!(value, "false" | "0")),
+                None => (token, true),
+            },
+        };
+        match name {
+            GENERATED => vec![(GENERATED, set)],
+            VENDORED => vec![(VENDORED, set)],
+            DIFF => vec![(DIFF, !set)],
+            "binary" if set => vec![(DIFF, true)],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn excludes(&self, path: &str) -> bool {
+        [GENERATED, VENDORED, DIFF].iter().any(|attribute| {
+            self.rules.iter().rev()
+                .find(|r| r.attribute == *attribute && r.path.is_match(path))
+                .is_some_and(|r| r.set)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gitattributes::GitAttributes;
+
+    #[test]
+    fn test_excluded_attributes() {
+        let mut attributes = GitAttributes::parse(r"
+            # generated sources
+            *.pb.go linguist-generated=true
+            vendor/** linguist-vendored
+            vendor/keep/** -linguist-vendored
+            *.lock -diff
+            *.png binary
+            docs/*.md linguist-documentation
+        ");
+        attributes.add("web", "dist/ linguist-generated\n*.min.js linguist-generated");
+        assert!(attributes.excludes("api/v1/service.pb.go"));
+        assert!(attributes.excludes("vendor/lib/lib.go"));
+        assert!(!attributes.excludes("vendor/keep/lib.go"));
+        assert!(attributes.excludes("Cargo.lock"));
+        assert!(attributes.excludes("assets/logo.png"));
+        assert!(!attributes.excludes("docs/index.md"));
+        assert!(attributes.excludes("web/dist/app.js"));
+        assert!(attributes.excludes("web/src/lib/jquery.min.js"));
+        assert!(!attributes.excludes("api/dist/app.js"));
+        assert!(!attributes.excludes("src/main.go"));
+    }
+}
@@ -0,0 +1,519 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+use serde::Deserialize;
+
+use changes::Changes;
+use cochanges::{CCMatrix, CoChanges};
+
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum GraphFormat {
+    GraphML,
+    Gexf,
+    Dot,
+}
+
+#[derive(Clone, Debug, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeWeight {
+    Freqs,
+    Probs,
+}
+
+#[derive(Clone, Debug)]
+pub struct GraphOpt {
+    pub weight: EdgeWeight,
+    pub min_weight: f64,
+    pub cluster_dirs: bool,
+}
+
+#[derive(Clone, Debug)]
+pub enum AttrValue {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Date(DateTime<Utc>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub id: String,
+    pub label: String,
+    pub cluster: Option<String>,
+    pub attributes: Vec<(String, AttrValue)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Edge {
+    pub source: usize,
+    pub target: usize,
+    pub weight: f64,
+}
+
+/// A weighted graph that can be written as GraphML, GEXF or Graphviz DOT.
+#[derive(Clone, Debug)]
+pub struct Graph {
+    pub directed: bool,
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+impl Default for GraphOpt {
+    fn default() -> Self {
+        GraphOpt {
+            weight: EdgeWeight::Probs,
+            min_weight: 0.0,
+            cluster_dirs: false,
+        }
+    }
+}
+
+impl Graph {
+    pub fn new(directed: bool) -> Graph {
+        Graph {
+            directed,
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        }
+    }
+
+    /// Builds the co-change graph: an edge goes from the changing file to the impacted file.
+    /// Only files with at least one edge above `opt.min_weight` are kept.
+    pub fn from_cochanges(cc: &CoChanges, changes: &Changes, opt: &GraphOpt) -> Graph {
+        let matrix: &CCMatrix = match opt.weight {
+            EdgeWeight::Freqs => &cc.freqs,
+            EdgeWeight::Probs => &cc.probs,
+        };
+        let mut graph = Graph::new(true);
+        let mut node_index = HashMap::<usize, usize>::new();
+        for ((impacted, changing), w) in matrix.matrix.indexed_iter() {
+            if impacted == changing || *w <= 0.0 || *w < opt.min_weight {
+                continue;
+            }
+            let source = graph.file_node(&mut node_index, changing, &matrix.row_names, changes, opt);
+            let target = graph.file_node(&mut node_index, impacted, &matrix.row_names, changes, opt);
+            graph.edges.push(Edge { source, target, weight: *w });
+        }
+        graph
+    }
+
+    fn file_node(
+        &mut self,
+        node_index: &mut HashMap<usize, usize>,
+        i: usize,
+        names: &[Rc<String>],
+        changes: &Changes,
+        opt: &GraphOpt,
+    ) -> usize {
+        if let Some(n) = node_index.get(&i) {
+            return *n;
+        }
+        let name = &names[i];
+        let directory = directory_of(name);
+        let mut attributes = vec![("directory".to_string(), AttrValue::Str(directory.clone()))];
+        if let Some(row) = changes.freqs.index_of_row(name) {
+            attributes.push(("changes".to_string(), AttrValue::Int(changes.c_freq[row] as i64)));
+            let last_change = changes.freqs.matrix.row(row)
+                .iter()
+                .enumerate()
+                .filter(|(_, x)| **x > 0.0)
+                .map(|(c, _)| changes.freqs.col_names[c])
+                .max();
+            if let Some(date) = last_change {
+                attributes.push(("last_change".to_string(), AttrValue::Date(date)));
+            }
+        }
+        self.nodes.push(Node {
+            id: format!("n{}", self.nodes.len()),
+            label: name.to_string(),
+            cluster: if opt.cluster_dirs { Some(directory) } else { None },
+            attributes,
+        });
+        node_index.insert(i, self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    pub fn write(&self, w: &mut dyn Write, format: GraphFormat) -> Result<()> {
+        match format {
+            GraphFormat::GraphML => self.write_graphml(w),
+            GraphFormat::Gexf => self.write_gexf(w),
+            GraphFormat::Dot => self.write_dot(w),
+        }
+    }
+
+    fn attribute_schema(&self) -> Vec<(String, &'static str)> {
+        let mut schema = Vec::<(String, &'static str)>::new();
+        for node in self.nodes.iter() {
+            for (name, value) in node.attributes.iter() {
+                if !schema.iter().any(|(n, _)| n == name) {
+                    schema.push((name.clone(), value.type_name()));
+                }
+            }
+        }
+        if self.nodes.iter().any(|n| n.cluster.is_some()) {
+            schema.push(("cluster".to_string(), "string"));
+        }
+        schema
+    }
+
+    fn node_values(node: &Node) -> Vec<(String, String)> {
+        let mut values = node.attributes.iter()
+            .map(|(name, value)| (name.clone(), value.to_string()))
+            .collect::<Vec<(String, String)>>();
+        if let Some(cluster) = &node.cluster {
+            values.push(("cluster".to_string(), cluster.clone()));
+        }
+        values
+    }
+
+    fn write_graphml(&self, w: &mut dyn Write) -> Result<()> {
+        let schema = self.attribute_schema();
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(w, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+        writeln!(w, r#"  <key id="label" for="node" attr.name="label" attr.type="string"/>"#)?;
+        for (i, (name, kind)) in schema.iter().enumerate() {
+            let kind = match *kind {
+                "integer" => "long",
+                k => k,
+            };
+            writeln!(w, r#"  <key id="d{i}" for="node" attr.name="{}" attr.type="{kind}"/>"#, xml_escape(name))?;
+        }
+        writeln!(w, r#"  <key id="weight" for="edge" attr.name="weight" attr.type="double"/>"#)?;
+        let edge_default = if self.directed { "directed" } else { "undirected" };
+        writeln!(w, r#"  <graph id="G" edgedefault="{edge_default}">"#)?;
+        for node in self.nodes.iter() {
+            writeln!(w, r#"    <node id="{}">"#, node.id)?;
+            writeln!(w, r#"      <data key="label">{}</data>"#, xml_escape(&node.label))?;
+            for (name, value) in Graph::node_values(node) {
+                let key = schema.iter().position(|(n, _)| *n == name).unwrap();
+                writeln!(w, r#"      <data key="d{key}">{}</data>"#, xml_escape(&value))?;
+            }
+            writeln!(w, "    </node>")?;
+        }
+        for edge in self.edges.iter() {
+            writeln!(
+                w,
+                r#"    <edge source="{}" target="{}"><data key="weight">{}</data></edge>"#,
+                self.nodes[edge.source].id, self.nodes[edge.target].id, edge.weight
+            )?;
+        }
+        writeln!(w, "  </graph>")?;
+        writeln!(w, "</graphml>")?;
+        Ok(())
+    }
+
+    fn write_gexf(&self, w: &mut dyn Write) -> Result<()> {
+        let schema = self.attribute_schema();
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(w, r#"<gexf xmlns="http://gexf.net/1.3" version="1.3">"#)?;
+        let edge_default = if self.directed { "directed" } else { "undirected" };
+        writeln!(w, r#"  <graph mode="static" defaultedgetype="{edge_default}">"#)?;
+        writeln!(w, r#"    <attributes class="node">"#)?;
+        for (i, (name, kind)) in schema.iter().enumerate() {
+            writeln!(w, r#"      <attribute id="{i}" title="{}" type="{kind}"/>"#, xml_escape(name))?;
+        }
+        writeln!(w, "    </attributes>")?;
+        writeln!(w, "    <nodes>")?;
+        for node in self.nodes.iter() {
+            writeln!(w, r#"      <node id="{}" label="{}">"#, node.id, xml_escape(&node.label))?;
+            writeln!(w, "        <attvalues>")?;
+            for (name, value) in Graph::node_values(node) {
+                let key = schema.iter().position(|(n, _)| *n == name).unwrap();
+                writeln!(w, r#"          <attvalue for="{key}" value="{}"/>"#, xml_escape(&value))?;
+            }
+            writeln!(w, "        </attvalues>")?;
+            writeln!(w, "      </node>")?;
+        }
+        writeln!(w, "    </nodes>")?;
+        writeln!(w, "    <edges>")?;
+        for (i, edge) in self.edges.iter().enumerate() {
+            writeln!(
+                w,
+                r#"      <edge id="{i}" source="{}" target="{}" weight="{}"/>"#,
+                self.nodes[edge.source].id, self.nodes[edge.target].id, edge.weight
+            )?;
+        }
+        writeln!(w, "    </edges>")?;
+        writeln!(w, "  </graph>")?;
+        writeln!(w, "</gexf>")?;
+        Ok(())
+    }
+
+    fn write_dot(&self, w: &mut dyn Write) -> Result<()> {
+        let (kind, arrow) = if self.directed { ("digraph", "->") } else { ("graph", "--") };
+        writeln!(w, "{kind} ccan {{")?;
+        writeln!(w, "  node [shape=box];")?;
+        let clusters = self.nodes.iter()
+            .enumerate()
+            .into_group_map_by(|(_, n)| n.cluster.clone());
+        for (cluster, nodes) in clusters.iter().sorted_by(|x, y| x.0.cmp(y.0)) {
+            let indent = match cluster {
+                Some(c) => {
+                    writeln!(w, "  subgraph \"cluster_{}\" {{", dot_escape(c))?;
+                    writeln!(w, "    label=\"{}\";", dot_escape(c))?;
+                    "    "
+                }
+                None => "  ",
+            };
+            for (_, node) in nodes {
+                let attrs = Graph::node_values(node).iter()
+                    .filter(|(name, _)| name != "cluster")
+                    .map(|(name, value)| format!("{name}=\"{}\"", dot_escape(value)))
+                    .join(", ");
+                writeln!(w, "{indent}{} [label=\"{}\", {attrs}];", node.id, dot_escape(&node.label))?;
+            }
+            if cluster.is_some() {
+                writeln!(w, "  }}")?;
+            }
+        }
+        for edge in self.edges.iter() {
+            writeln!(
+                w,
+                "  {} {arrow} {} [weight=\"{}\", label=\"{:.2}\"];",
+                self.nodes[edge.source].id, self.nodes[edge.target].id, edge.weight, edge.weight
+            )?;
+        }
+        writeln!(w, "}}")?;
+        Ok(())
+    }
+}
+
+impl AttrValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            AttrValue::Int(_) => "integer",
+            AttrValue::Float(_) => "double",
+            AttrValue::Str(_) | AttrValue::Date(_) => "string",
+        }
+    }
+}
+
+impl Display for AttrValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttrValue::Int(i) => write!(f, "{i}"),
+            AttrValue::Float(x) => write!(f, "{x}"),
+            AttrValue::Str(s) => write!(f, "{s}"),
+            AttrValue::Date(d) => write!(f, "{}", d.format("%Y-%m-%d")),
+        }
+    }
+}
+
+pub fn directory_of(path: &str) -> String {
+    match Path::new(path).parent().and_then(|p| p.to_str()) {
+        Some("") | None => ".".to_string(),
+        Some(p) => p.to_string(),
+    }
+}
+
+pub(crate) fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl GraphFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            GraphFormat::GraphML => "graphml",
+            GraphFormat::Gexf => "gexf",
+            GraphFormat::Dot => "dot",
+        }
+    }
+}
+
+impl Display for GraphFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+impl FromStr for GraphFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "graphml" => Ok(GraphFormat::GraphML),
+            "gexf" => Ok(GraphFormat::Gexf),
+            "dot" | "graphviz" => Ok(GraphFormat::Dot),
+            _ => bail!("cannot parse GraphFormat from {}", s),
+        }
+    }
+}
+
+impl Display for EdgeWeight {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            EdgeWeight::Freqs => "freqs",
+            EdgeWeight::Probs => "probs",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl FromStr for EdgeWeight {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "freqs" => Ok(EdgeWeight::Freqs),
+            "probs" => Ok(EdgeWeight::Probs),
+            _ => bail!("cannot parse EdgeWeight from {}", s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use regex::Regex;
+
+    use bettergit::diffs_of;
+    use changes::Changes;
+    use cochanges::{CoChanges, CoChangesOpt};
+    use model::ModelTypes;
+
+    use crate::graph::{AttrValue, Edge, EdgeWeight, Graph, GraphFormat, GraphOpt, Node};
+
+    /// Four transactions changing {a, b}, {a, b, c}, {a} and {c}, with P(a | b) = 1 and P(b | a) = 2/3.
+    fn co_changes() -> (CoChanges, Changes) {
+        let changes = Changes::from_diffs(diffs_of(&[
+            (0, "Alice", "src/a.rs"), (0, "Alice", "src/b.rs"),
+            (1, "Alice", "src/a.rs"), (1, "Alice", "src/b.rs"), (1, "Alice", "lib/c.rs"),
+            (2, "Alice", "src/a.rs"), (3, "Alice", "lib/c.rs"),
+        ]));
+        let opts = CoChangesOpt { changes_min: 0, freq_min: 0, algorithm: ModelTypes::Bayes };
+        (CoChanges::from_changes(&changes, &opts), changes)
+    }
+
+    fn labels(graph: &Graph) -> Vec<&str> {
+        let mut labels = graph.nodes.iter().map(|n| n.label.as_str()).collect::<Vec<_>>();
+        labels.sort();
+        labels
+    }
+
+    fn values(node: &Node) -> HashMap<String, String> {
+        Graph::node_values(node).into_iter().collect()
+    }
+
+    fn node(id: &str, cluster: Option<&str>) -> Node {
+        Node {
+            id: id.to_string(),
+            label: format!("src/{id}.rs"),
+            cluster: cluster.map(String::from),
+            attributes: vec![("changes".to_string(), AttrValue::Int(3))],
+        }
+    }
+
+    #[test]
+    fn test_dot_clusters() {
+        let mut graph = Graph::new(true);
+        graph.nodes.push(node("a", Some("src")));
+        graph.nodes.push(node("b", None));
+        graph.edges.push(Edge { source: 0, target: 1, weight: 0.5 });
+        let mut out = Vec::new();
+        graph.write(&mut out, GraphFormat::Dot).unwrap();
+        let dot = String::from_utf8(out).unwrap();
+        assert!(dot.contains("subgraph \"cluster_src\""));
+        assert!(dot.contains("a -> b"));
+        assert!(!dot.contains("cluster=\""));
+    }
+
+    #[test]
+    fn test_from_cochanges() {
+        let (cc, changes) = co_changes();
+        let graph = |weight: EdgeWeight, min_weight: f64| {
+            Graph::from_cochanges(&cc, &changes, &GraphOpt { weight, min_weight, cluster_dirs: false })
+        };
+        let all = graph(EdgeWeight::Probs, 0.0);
+        assert_eq!(vec!["lib/c.rs", "src/a.rs", "src/b.rs"], labels(&all));
+        assert_eq!(6, all.edges.len());
+        // only a and b change together with P >= 0.6, in either direction
+        let coupled = graph(EdgeWeight::Probs, 0.6);
+        assert_eq!(vec!["src/a.rs", "src/b.rs"], labels(&coupled));
+        let b_to_a = coupled.edges.iter().find(|e| coupled.nodes[e.source].label == "src/b.rs").unwrap();
+        assert_eq!(("src/a.rs", 1.0), (coupled.nodes[b_to_a.target].label.as_str(), b_to_a.weight));
+        assert_eq!(2, coupled.edges.len());
+        // and twice, every other pair once
+        let frequent = graph(EdgeWeight::Freqs, 2.0);
+        assert_eq!(vec!["src/a.rs", "src/b.rs"], labels(&frequent));
+        assert!(frequent.edges.iter().all(|e| e.weight == 2.0));
+        assert!(graph(EdgeWeight::Freqs, 3.0).nodes.is_empty());
+
+        let a = all.nodes.iter().find(|n| n.label == "src/a.rs").unwrap();
+        let expected = [("directory", "src"), ("changes", "3"), ("last_change", "2023-01-03")].iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(expected, values(a));
+        assert!(a.cluster.is_none());
+        let clustered = Graph::from_cochanges(&cc, &changes, &GraphOpt { cluster_dirs: true, ..GraphOpt::default() });
+        assert!(clustered.nodes.iter().all(|n| n.cluster.as_deref() == n.label.split('/').next()));
+    }
+
+    #[test]
+    fn test_xml_attributes_roundtrip() {
+        let (cc, changes) = co_changes();
+        let graph = Graph::from_cochanges(&cc, &changes, &GraphOpt { cluster_dirs: true, ..GraphOpt::default() });
+        let formats = [
+            (GraphFormat::GraphML, r#"<key id="(\w+)" for="node" attr.name="([^"]+)""#, r#"<node id="(\w+)">"#, r#"<data key="(\w+)">([^<]*)</data>"#),
+            (GraphFormat::Gexf, r#"<attribute id="(\w+)" title="([^"]+)""#, r#"<node id="(\w+)""#, r#"<attvalue for="(\w+)" value="([^"]*)"/>"#),
+        ];
+        for (format, key, node, value) in formats {
+            let mut out = Vec::new();
+            graph.write(&mut out, format).unwrap();
+            let xml = String::from_utf8(out).unwrap();
+            // reads back the declared attributes and the values written for each node
+            let keys = Regex::new(key).unwrap().captures_iter(&xml)
+                .map(|c| (c[1].to_string(), c[2].to_string()))
+                .collect::<HashMap<_, _>>();
+            let (node, value) = (Regex::new(node).unwrap(), Regex::new(value).unwrap());
+            let mut read = HashMap::<String, HashMap<String, String>>::new();
+            let mut current = None;
+            for line in xml.lines() {
+                if let Some(c) = node.captures(line) {
+                    current = Some(c[1].to_string());
+                } else if let (Some(c), Some(n)) = (value.captures(line), current.as_ref()) {
+                    if let Some(name) = keys.get(&c[1]) {
+                        read.entry(n.clone()).or_default().insert(name.clone(), c[2].to_string());
+                    }
+                }
+            }
+            assert_eq!(3, read.len(), "{}", format);
+            for n in graph.nodes.iter() {
+                let mut expected = values(n);
+                if format == GraphFormat::GraphML {
+                    expected.insert("label".to_string(), n.label.clone());
+                }
+                assert_eq!(expected, read[&n.id], "{} node {}", format, n.label);
+            }
+        }
+    }
+
+    #[test]
+    fn test_xml_escaping() {
+        let mut graph = Graph::new(false);
+        let mut n = node("a", None);
+        n.label = "<a&b>".to_string();
+        graph.nodes.push(n);
+        for format in [GraphFormat::GraphML, GraphFormat::Gexf] {
+            let mut out = Vec::new();
+            graph.write(&mut out, format).unwrap();
+            let xml = String::from_utf8(out).unwrap();
+            assert!(xml.contains("&lt;a&amp;b&gt;"), "{} not escaped", format);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use git2::{ObjectType, Repository, TreeWalkMode, TreeWalkResult};
+use itertools::Itertools;
+use log::{debug, info};
+use serde::Deserialize;
+
+use changes::Changes;
+use symbols::SymbolParser;
+
+const DISPLAYED_HOTSPOTS: usize = 20;
+const TAB_WIDTH: usize = 4;
+
+/// The size or complexity measure multiplied by the changes of a file to rank hotspots.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HotspotMetric {
+    /// Non-blank lines.
+    #[default]
+    Loc,
+    /// Total indentation of the non-blank lines, in levels of four spaces or one tab.
+    Indentation,
+    /// One plus the decision points, for the languages supported by `SymbolParser`.
+    Cyclomatic,
+}
+
+impl FromStr for HotspotMetric {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "loc" => Ok(HotspotMetric::Loc),
+            "indentation" => Ok(HotspotMetric::Indentation),
+            "cyclomatic" => Ok(HotspotMetric::Cyclomatic),
+            _ => bail!("cannot parse HotspotMetric from {}", s)
+        }
+    }
+}
+
+impl Display for HotspotMetric {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            HotspotMetric::Loc => "loc",
+            HotspotMetric::Indentation => "indentation",
+            HotspotMetric::Cyclomatic => "cyclomatic",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct HotspotOpt {
+    pub enabled: bool,
+    pub metric: HotspotMetric,
+    /// Compute the cyclomatic complexity even when ranking by another metric.
+    pub cyclomatic: bool,
+    /// Number of equally long periods the mined history is split into to compute trends.
+    pub periods: usize,
+}
+
+impl Default for HotspotOpt {
+    fn default() -> Self {
+        HotspotOpt {
+            enabled: false,
+            metric: HotspotMetric::Loc,
+            cyclomatic: false,
+            periods: 4,
+        }
+    }
+}
+
+/// Size and complexity of a file at the analysed revision.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileMetrics {
+    pub loc: usize,
+    pub indentation: f64,
+    pub mean_indentation: f64,
+    pub max_indentation: f64,
+    pub cyclomatic: Option<usize>,
+}
+
+impl FileMetrics {
+    pub fn measure(path: &str, source: &[u8], parser: Option<&mut SymbolParser>) -> FileMetrics {
+        let text = String::from_utf8_lossy(source);
+        let levels = text.lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| {
+                let spaces = l.chars()
+                    .take_while(|c| c.is_whitespace())
+                    .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+                    .sum::<usize>();
+                spaces as f64 / TAB_WIDTH as f64
+            })
+            .collect::<Vec<f64>>();
+        let indentation = levels.iter().fold(0.0, |total, l| total + l);
+        FileMetrics {
+            loc: levels.len(),
+            indentation,
+            mean_indentation: if levels.is_empty() { 0.0 } else { indentation / levels.len() as f64 },
+            max_indentation: levels.iter().cloned().fold(0.0, f64::max),
+            cyclomatic: parser.and_then(|p| p.cyclomatic(path, source)),
+        }
+    }
+
+    pub fn value(&self, metric: HotspotMetric) -> f64 {
+        match metric {
+            HotspotMetric::Loc => self.loc as f64,
+            HotspotMetric::Indentation => self.indentation,
+            HotspotMetric::Cyclomatic => self.cyclomatic.unwrap_or(0) as f64,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Hotspot {
+    pub file: String,
+    /// Transactions changing the file.
+    pub changes: i32,
+    /// Sum of the file's row of `Changes.freqs`, i.e. its churn when changes are weighted by churn.
+    pub churn: f64,
+    pub metrics: FileMetrics,
+    pub score: f64,
+    /// Transactions changing the file in each period, oldest first.
+    pub trend: Vec<i32>,
+    /// Least-squares slope of the trend, in changes per period.
+    pub slope: f64,
+}
+
+/// Files that change often and are large or complex, highest scores first.
+pub struct Hotspots {
+    pub metric: HotspotMetric,
+    /// Start of each trend period.
+    pub periods: Vec<DateTime<Utc>>,
+    pub hotspots: Vec<Hotspot>,
+}
+
+impl Hotspots {
+    /// Measures the files of `changes` that still exist at the revision of the repository.
+    pub fn from_repository(repo: &Repository, revision: &str, changes: &Changes, opt: &HotspotOpt) -> Result<Hotspots> {
+        let tree = match repo.revparse_single(revision) {
+            Ok(obj) => obj.peel_to_tree()?,
+            Err(e) => bail!("cannot find revision {}: {}", revision, e.message()),
+        };
+        let mut blobs = Vec::new();
+        tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() == Some(ObjectType::Blob) {
+                let path = format!("{}{}", dir, entry.name().unwrap_or(""));
+                if changes.freqs.index_of_row(&path.clone().into()).is_some() {
+                    blobs.push((path, entry.id()));
+                }
+            }
+            TreeWalkResult::Ok
+        })?;
+        debug!("Measuring {} changed files at {}", blobs.len(), revision);
+        let mut parser = SymbolParser::new();
+        let cyclomatic = opt.cyclomatic || opt.metric == HotspotMetric::Cyclomatic;
+        let mut metrics = Vec::new();
+        for (path, oid) in blobs {
+            let blob = repo.find_blob(oid)?;
+            if blob.is_binary() {
+                continue;
+            }
+            let parser = if cyclomatic { Some(&mut parser) } else { None };
+            let measured = FileMetrics::measure(&path, blob.content(), parser);
+            metrics.push((path, measured));
+        }
+        let hotspots = Hotspots::from_metrics(changes, metrics, opt);
+        info!("Ranked {} hotspots by {}", hotspots.hotspots.len(), opt.metric);
+        Ok(hotspots)
+    }
+
+    pub fn from_metrics(changes: &Changes, metrics: Vec<(String, FileMetrics)>, opt: &HotspotOpt) -> Hotspots {
+        let freqs = &changes.freqs;
+        let periods = Hotspots::period_starts(&freqs.col_names, opt.periods);
+        let period_of = |d: &DateTime<Utc>| periods.iter().rposition(|p| p <= d).unwrap_or(0);
+        let hotspots = metrics.into_iter()
+            .filter_map(|(file, metrics)| {
+                let row = freqs.index_of_row(&file.clone().into())?;
+                let churn = freqs.matrix.row(row).sum();
+                let mut trend = vec![0; periods.len()];
+                for (c, x) in freqs.matrix.row(row).iter().enumerate() {
+                    if *x > 0.0 {
+                        trend[period_of(&freqs.col_names[c])] += 1;
+                    }
+                }
+                Some(Hotspot {
+                    file,
+                    changes: changes.c_freq[row],
+                    churn,
+                    score: churn * metrics.value(opt.metric),
+                    slope: slope(&trend),
+                    trend,
+                    metrics,
+                })
+            })
+            .sorted_by(|x, y| y.score.total_cmp(&x.score).then(x.file.cmp(&y.file)))
+            .collect();
+        Hotspots { metric: opt.metric, periods, hotspots }
+    }
+
+    fn period_starts(dates: &[DateTime<Utc>], periods: usize) -> Vec<DateTime<Utc>> {
+        let (first, last) = match (dates.iter().min(), dates.iter().max()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return Vec::new(),
+        };
+        let periods = periods.max(1) as i32;
+        let length = (last - first) / periods;
+        (0..periods).map(|i| first + length * i).collect()
+    }
+}
+
+fn slope(ys: &[i32]) -> f64 {
+    let n = ys.len() as f64;
+    if ys.len() < 2 {
+        return 0.0;
+    }
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = ys.iter().sum::<i32>() as f64 / n;
+    let (mut cov, mut var) = (0.0, 0.0);
+    for (x, y) in ys.iter().enumerate() {
+        cov += (x as f64 - mean_x) * (*y as f64 - mean_y);
+        var += (x as f64 - mean_x).powi(2);
+    }
+    cov / var
+}
+
+impl Display for Hotspots {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Hotspots by changes x {}", self.metric)?;
+        writeln!(f, "     Score  Changes  {:>11}  Trend         File", self.metric.to_string())?;
+        for h in self.hotspots.iter().take(DISPLAYED_HOTSPOTS) {
+            let trend = match h.slope {
+                s if s > 0.5 => "rising",
+                s if s < -0.5 => "falling",
+                _ => "stable",
+            };
+            writeln!(
+                f, "{:>10.1}  {:>7}  {:>11.1}  {:<12}  {}",
+                h.score, h.changes, h.metrics.value(self.metric), trend, h.file
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    use changes::Changes;
+    use hotspots::{slope, FileMetrics, HotspotMetric, HotspotOpt, Hotspots};
+    use matrix::NamedMatrix;
+    use symbols::SymbolParser;
+
+    #[test]
+    fn test_hotspots() {
+        let source = "fn main() {\n    if true {\n\tprintln!();\n    }\n\n}\n";
+        let metrics = FileMetrics::measure("main.rs", source.as_bytes(), Some(&mut SymbolParser::new()));
+        assert_eq!(5, metrics.loc);
+        assert_eq!(3.0, metrics.indentation);
+        assert_eq!(0.6, metrics.mean_indentation);
+        assert_eq!(Some(2), metrics.cyclomatic);
+
+        let files = ["a.rs", "b.rs"];
+        let start = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        let dates = (0..8).map(|d| start + Duration::days(d)).collect::<Vec<_>>();
+        let mut freqs = NamedMatrix::new(files.iter().map(|f| Rc::new(f.to_string())).collect(), dates, Some("files"), Some("dates"));
+        // a.rs changes more and more often, b.rs only at the beginning
+        for (i, j) in [(0, 3), (0, 5), (0, 6), (0, 7), (1, 0), (1, 1)] {
+            freqs.matrix[[i, j]] = 1.0;
+        }
+        let metrics = vec![
+            ("a.rs".to_string(), FileMetrics { loc: 10, ..FileMetrics::default() }),
+            ("b.rs".to_string(), FileMetrics { loc: 100, ..FileMetrics::default() }),
+            ("deleted.rs".to_string(), FileMetrics { loc: 100, ..FileMetrics::default() }),
+        ];
+        let opt = HotspotOpt { periods: 2, metric: HotspotMetric::Loc, ..HotspotOpt::default() };
+        let hotspots = Hotspots::from_metrics(&Changes::from_freqs(freqs), metrics, &opt);
+        assert_eq!(vec!["b.rs", "a.rs"], hotspots.hotspots.iter().map(|h| h.file.as_str()).collect::<Vec<_>>());
+        assert_eq!(200.0, hotspots.hotspots[0].score);
+        assert_eq!(vec![2, 0], hotspots.hotspots[0].trend);
+        assert_eq!(vec![1, 3], hotspots.hotspots[1].trend);
+        assert_eq!(2.0, hotspots.hotspots[1].slope);
+        assert_eq!(0.0, slope(&[1, 1, 1]));
+    }
+}
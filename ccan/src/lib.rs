@@ -1,29 +1,64 @@
 extern crate anyhow;
+extern crate bincode;
 extern crate chrono;
+extern crate flate2;
 extern crate git2;
 extern crate itertools;
 extern crate log;
 extern crate ndarray;
 extern crate regex;
+extern crate serde;
+extern crate serde_yaml;
+extern crate toml;
+extern crate tree_sitter;
+extern crate tree_sitter_java;
+extern crate tree_sitter_python;
+extern crate tree_sitter_rust;
+extern crate tree_sitter_typescript;
 
 use anyhow::{bail, Result};
 use chrono::{DateTime, Duration, Utc};
 use git2::Repository;
+use log::info;
+use serde::Deserialize;
 
+use architecture::{Architecture, ArchitectureOpt, ChangeHistory, Violations};
 use cochanges::{CoChanges, CoChangesOpt};
+use communities::{Communities, CommunityOpt};
+use components::ComponentGrouping;
+use dependencies::{DependencyOpt, HiddenDependencies, ReferenceGraph};
+use coordination::{Authorship, Coordination, CoordinationOpt};
+use hotspots::{HotspotOpt, Hotspots};
+use ownership::{Ownership, OwnershipOpt};
 use predict::{PredictionOpt, RippleChangeProbabilities};
+use symbols::{file_of, Granularity};
 
-use crate::bettergit::{BetterGit, BetterGitOpt};
+use crate::bettergit::{read_ignore_file, BetterGit, BetterGitOpt, GroupedBetterDiffs, IGNORE_FILE_NAME};
 use crate::changes::Changes;
 
+pub mod architecture;
 pub mod bayes;
 pub mod bettergit;
 pub mod changes;
 pub mod cochanges;
+pub mod communities;
+pub mod components;
+pub mod config;
+pub mod coordination;
+pub mod dependencies;
+pub mod conventional;
+pub mod evaluate;
+pub mod gitattributes;
+pub mod graph;
+pub mod hotspots;
 pub mod matrix;
 pub mod model;
 pub mod naive;
+pub mod ownership;
 pub mod predict;
+pub mod report;
+pub mod symbols;
+pub mod transactions;
 pub mod nop;
 
 pub enum AnalysisStatus {
@@ -43,18 +78,35 @@ pub struct Analysis {
     pub status: AnalysisStatus,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
 pub struct Options {
     pub repository: String,
+    #[serde(rename = "git")]
     pub git_opts: BetterGitOpt,
+    #[serde(rename = "cochanges")]
     pub cc_opts: CoChangesOpt,
+    #[serde(rename = "prediction")]
     pub pred_opts: PredictionOpt,
+    pub components: ComponentGrouping,
+    pub hotspots: HotspotOpt,
+    pub ownership: OwnershipOpt,
+    pub coordination: CoordinationOpt,
+    pub communities: CommunityOpt,
+    pub architecture: ArchitectureOpt,
+    pub dependencies: DependencyOpt,
 }
 
 pub struct AnalysisOutput {
     pub changes: Changes,
     pub co_changes: CoChanges,
     pub ripples: RippleChangeProbabilities,
+    pub hotspots: Option<Hotspots>,
+    pub ownership: Option<Ownership>,
+    pub coordination: Option<Coordination>,
+    pub communities: Option<Communities>,
+    pub violations: Option<Violations>,
+    pub hidden_dependencies: Option<HiddenDependencies>,
 }
 
 impl Analysis {
@@ -90,15 +142,120 @@ impl Analysis {
     }
 
     fn execute(opt: &Options) -> Result<AnalysisOutput> {
+        let architecture = match &opt.architecture.file {
+            Some(file) => Some(Architecture::from_file(file)?),
+            None => None,
+        };
+        let diffs = mine_diffs(&opt.repository, &opt.git_opts)?;
+        let head = opt.git_opts.commit_filters.head();
+        let mapper = opt.components.mapper(Some(&opt.repository), &head)?;
+        let mut ownership = if opt.ownership.enabled {
+            Some(Ownership::from_diffs(&diffs, &opt.ownership))
+        } else {
+            None
+        };
+        let authorship = if opt.coordination.enabled {
+            Some(Authorship::from_diffs(&diffs))
+        } else {
+            None
+        };
+        let history = architecture.as_ref().map(|_| ChangeHistory::from_diffs(&diffs, |f| match &mapper {
+            Some(mapper) => mapper.component_of(f),
+            None => Some(f.to_string()),
+        }));
+        let changes = Changes::from_weighted_diffs(diffs, opt.git_opts.churn);
         let repo = Repository::open(&opt.repository)?;
-        let diffs = repo.mine_diffs(&opt.git_opts)?;
-        let changes = Changes::from_diffs(diffs);
-        let co_changes = CoChanges::from_changes(&changes, &opt.cc_opts);
-        let predictions = RippleChangeProbabilities::from(&co_changes, &changes, &opt.pred_opts);
+        let hotspots = if opt.hotspots.enabled {
+            // symbols are measured as the files defining them
+            let by_file = match opt.git_opts.granularity {
+                Granularity::Symbols => Some(changes.aggregate(|f| Some(file_of(f).to_string()))),
+                Granularity::Files => None,
+            };
+            Some(Hotspots::from_repository(&repo, &head, by_file.as_ref().unwrap_or(&changes), &opt.hotspots)?)
+        } else {
+            None
+        };
+        let mut pred_opts = opt.pred_opts.clone();
+        let (changes, components) = match &mapper {
+            Some(mapper) => (mapper.aggregate(&changes), mapper.components(&changes)),
+            None => (changes, Default::default()),
+        };
+        let mut co_changes = CoChanges::from_changes(&changes, &opt.cc_opts);
+        co_changes.components = components;
+        pred_opts.changed_files = co_changes.map_files(&pred_opts.changed_files);
+        if let Some(ownership) = ownership.as_mut() {
+            ownership.flag_crossings(&co_changes, opt.ownership.min_probability);
+        }
+        let coordination = authorship
+            .map(|authorship| Coordination::from_authorship(authorship, &co_changes, &opt.coordination));
+        let communities = if opt.communities.enabled {
+            Some(Communities::from_cochanges(&co_changes, &opt.communities))
+        } else {
+            None
+        };
+        let violations = match (&architecture, &history) {
+            (Some(architecture), Some(history)) => {
+                Some(Violations::detect(architecture, &co_changes, history, &opt.architecture))
+            }
+            _ => None,
+        };
+        let hidden_dependencies = if opt.dependencies.enabled {
+            let graph = ReferenceGraph::from_repository(&repo, &head)?;
+            let graph = match &mapper {
+                Some(mapper) => graph.aggregate(|f| mapper.component_of(f)),
+                None => graph,
+            };
+            Some(HiddenDependencies::detect(&co_changes, &graph, &opt.dependencies))
+        } else {
+            None
+        };
+        let mut predictions = RippleChangeProbabilities::from(&co_changes, &changes, &pred_opts);
+        let existing = repo.files_at(&head)?;
+        let existing = match &mapper {
+            Some(mapper) => existing.iter().filter_map(|f| mapper.component_of(f)).collect(),
+            None => existing,
+        };
+        predictions.clear_files(|f| {
+            let file = match (&mapper, opt.git_opts.granularity) {
+                (None, Granularity::Symbols) => file_of(f),
+                _ => f,
+            };
+            existing.contains(file)
+        });
         Ok(AnalysisOutput {
             changes,
             co_changes,
             ripples: predictions,
+            hotspots,
+            ownership,
+            coordination,
+            communities,
+            violations,
+            hidden_dependencies,
         })
     }
 }
+
+/// Revision ranges between consecutive tags matching the glob, oldest first.
+pub fn tag_ranges(repository: &str, pattern: Option<&str>) -> Result<Vec<String>> {
+    Repository::open(repository)?.tag_ranges(pattern)
+}
+
+pub fn mine_changes(repository: &str, git_opts: &BetterGitOpt) -> Result<Changes> {
+    let diffs = mine_diffs(repository, git_opts)?;
+    Ok(Changes::from_weighted_diffs(diffs, git_opts.churn))
+}
+
+pub fn mine_diffs(repository: &str, git_opts: &BetterGitOpt) -> Result<GroupedBetterDiffs> {
+    let repo = Repository::open(repository)?;
+    let ignore_file = repo.workdir().map(|d| d.join(IGNORE_FILE_NAME)).filter(|f| f.is_file());
+    match ignore_file {
+        Some(ignore_file) => {
+            info!("Excluding files listed in {}", ignore_file.display());
+            let mut git_opts = git_opts.clone();
+            git_opts.file_filters = git_opts.file_filters.exclude_more(&read_ignore_file(&ignore_file)?)?;
+            repo.mine_diffs(&git_opts)
+        }
+        None => repo.mine_diffs(git_opts),
+    }
+}
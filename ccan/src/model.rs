@@ -4,6 +4,7 @@ use std::{
 };
 
 use anyhow::{bail, Error};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     bayes::{BayesianModel, MixedModel}, cochanges::{CCFreqsCalculator, CCProbsCalculator}, naive::NaiveModel, nop::NopModel, predict::RippleChangePredictor
@@ -11,8 +12,10 @@ use crate::{
 
 pub trait Model: CCFreqsCalculator + CCProbsCalculator + RippleChangePredictor {}
 
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ModelTypes {
+    #[default]
     Naive,
     Bayes,
     Mixed,
@@ -54,8 +54,9 @@ impl NaiveModel {
                 continue;
             }
             for j in (0..=i).rev() {
-                if (f2[j] - 1f64).abs() < 1e-5 {
-                    coeff = coeff + dates_dist[[i, j]];
+                if f2[j] > 1e-5 {
+                    // churn-weighted changes count as the geometric mean of their weights
+                    coeff = coeff + dates_dist[[i, j]] * (f1[i] * f2[j]).sqrt();
                 }
             }
         }
@@ -65,16 +66,14 @@ impl NaiveModel {
 
 impl CCFreqsCalculator for NaiveModel {
     fn calculate_freqs(&self, changes: &Changes, opts: &CoChangesOpt) -> CCMatrix {
+        let c_freq = &changes.c_freq;
         let changes = &changes.freqs;
-        let min_change_freq = opts.changes_min as f64;
-        let mut filt_row_names = Vec::<Rc<String>>::new();
-        for row in changes.row_names.iter() {
-            if let Some(i) = changes.index_of_row(row) {
-                if changes.matrix.row(i).sum() >= min_change_freq {
-                    filt_row_names.push(row.clone());
-                }
-            }
-        }
+        let min_change_freq = opts.changes_min as i32;
+        // the files changed often enough, by their row in `changes`
+        let rows = (0..changes.row_names.len())
+            .filter(|i| c_freq[*i] >= min_change_freq)
+            .collect::<Vec<usize>>();
+        let filt_row_names = rows.iter().map(|i| changes.row_names[*i].clone()).collect::<Vec<Rc<String>>>();
 
         let n = filt_row_names.len();
         let mut cc_freq = CCMatrix::new(
@@ -90,12 +89,12 @@ impl CCFreqsCalculator for NaiveModel {
         let dates_dist = Self::dates_distance(&changes.col_names, |x| x.assign_elem(x.sqrt()));
         debug!("Calculating co-change coefficient");
         for i in 0..n {
-            let row_i = changes.matrix.row(i);
-            for j in 0..n {
+            let row_i = changes.matrix.row(rows[i]);
+            for (j, row) in rows.iter().enumerate() {
                 if i == j {
                     continue;
                 }
-                let row_j = changes.matrix.row(j);
+                let row_j = changes.matrix.row(*row);
                 cc_freq.matrix[[i, j]] = Self::cc_coefficient(&row_i, &row_j, &dates_dist);
             }
         }
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use anyhow::bail;
+use chrono::{DateTime, Duration, Utc};
+use itertools::Itertools;
+use serde::Deserialize;
+
+use bettergit::{Contribution, GroupedBetterDiffs};
+use cochanges::CoChanges;
+use config::deserialize_optional_since;
+use symbols::file_of;
+
+/// Days without commits after which an author is considered gone, when no date is given.
+pub const DEFAULT_INACTIVE_DAYS: i64 = 180;
+const DISPLAYED_ENTRIES: usize = 10;
+
+/// What the contribution of an author to a file is measured in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OwnershipMeasure {
+    #[default]
+    Commits,
+    /// Lines inserted and deleted.
+    Churn,
+}
+
+impl FromStr for OwnershipMeasure {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "commits" => Ok(OwnershipMeasure::Commits),
+            "churn" => Ok(OwnershipMeasure::Churn),
+            _ => bail!("cannot parse OwnershipMeasure from {}", s)
+        }
+    }
+}
+
+impl Display for OwnershipMeasure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            OwnershipMeasure::Commits => "commits",
+            OwnershipMeasure::Churn => "churn",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct OwnershipOpt {
+    pub enabled: bool,
+    pub measure: OwnershipMeasure,
+    /// Authors without commits since this date have left, by default `DEFAULT_INACTIVE_DAYS`
+    /// before the newest mined commit.
+    #[serde(deserialize_with = "deserialize_optional_since")]
+    pub inactive_since: Option<DateTime<Utc>>,
+    /// Co-change probability above which pairs owned by different authors are flagged.
+    pub min_probability: f64,
+}
+
+impl Default for OwnershipOpt {
+    fn default() -> Self {
+        OwnershipOpt {
+            enabled: false,
+            measure: OwnershipMeasure::Commits,
+            inactive_since: None,
+            min_probability: 0.5,
+        }
+    }
+}
+
+/// The authors of a file or directory and their share of its contributions, largest first.
+#[derive(Clone, Debug)]
+pub struct Owners {
+    pub name: String,
+    pub shares: Vec<(String, f64)>,
+    /// Smallest number of authors accounting for more than half of the contributions.
+    pub bus_factor: usize,
+    /// Share of the contributions made by authors who left.
+    pub knowledge_loss: f64,
+}
+
+impl Owners {
+    fn from_contributions(name: String, contributions: &HashMap<String, f64>, departed: &[String]) -> Owners {
+        let total = contributions.values().fold(0.0, |total, c| total + c);
+        let shares = contributions.iter()
+            .map(|(author, c)| (author.clone(), if total > 0.0 { c / total } else { 0.0 }))
+            .sorted_by(|x, y| y.1.total_cmp(&x.1).then(x.0.cmp(&y.0)))
+            .collect::<Vec<(String, f64)>>();
+        let mut covered = 0.0;
+        let bus_factor = shares.iter()
+            .take_while(|(_, share)| {
+                let uncovered = covered <= 0.5;
+                covered += share;
+                uncovered
+            })
+            .count();
+        let knowledge_loss = shares.iter()
+            .filter(|(author, _)| departed.contains(author))
+            .fold(0.0, |loss, (_, share)| loss + share);
+        Owners { name, shares, bus_factor, knowledge_loss }
+    }
+
+    pub fn owner(&self) -> Option<&(String, f64)> {
+        self.shares.first()
+    }
+}
+
+/// Files changed together often whose main owners differ.
+#[derive(Clone, Debug)]
+pub struct CrossingCoupling {
+    pub files: (String, String),
+    pub owners: (String, String),
+    /// Co-change probability of the files, see `CoChanges::coupled_pairs`.
+    pub probability: f64,
+}
+
+pub struct Ownership {
+    pub measure: OwnershipMeasure,
+    pub inactive_since: DateTime<Utc>,
+    /// Authors without commits since `inactive_since`.
+    pub departed: Vec<String>,
+    pub files: Vec<Owners>,
+    /// Shares in each directory, including the files of its subdirectories; `.` is the whole repository.
+    pub directories: Vec<Owners>,
+    pub crossings: Vec<CrossingCoupling>,
+}
+
+impl Ownership {
+    pub fn from_diffs(diffs: &GroupedBetterDiffs, opt: &OwnershipOpt) -> Ownership {
+        let contributions = diffs.values()
+            .flat_map(|d| d.contributions.iter())
+            .collect::<Vec<&Contribution>>();
+        let mut last_commits = HashMap::<String, DateTime<Utc>>::new();
+        for c in contributions.iter() {
+            let last = last_commits.entry(c.commit.author.clone()).or_insert(c.commit.when);
+            *last = (*last).max(c.commit.when);
+        }
+        let newest = last_commits.values().max().cloned().unwrap_or_else(Utc::now);
+        let inactive_since = opt.inactive_since
+            .unwrap_or(newest - Duration::days(DEFAULT_INACTIVE_DAYS));
+        let departed = last_commits.iter()
+            .filter(|(_, last)| **last < inactive_since)
+            .map(|(author, _)| author.clone())
+            .sorted()
+            .collect::<Vec<String>>();
+
+        let mut files = HashMap::<String, HashMap<String, f64>>::new();
+        let mut directories = HashMap::<String, HashMap<String, f64>>::new();
+        for c in contributions {
+            let amount = match opt.measure {
+                OwnershipMeasure::Commits => 1.0,
+                OwnershipMeasure::Churn => (c.insertions + c.deletions) as f64,
+            };
+            *files.entry(c.file.to_string()).or_default()
+                .entry(c.commit.author.clone()).or_default() += amount;
+            for dir in directories_of(&c.file) {
+                *directories.entry(dir).or_default()
+                    .entry(c.commit.author.clone()).or_default() += amount;
+            }
+        }
+        let owners = |entries: HashMap<String, HashMap<String, f64>>| entries.into_iter()
+            .map(|(name, c)| Owners::from_contributions(name, &c, &departed))
+            .sorted_by(|x, y| x.name.cmp(&y.name))
+            .collect::<Vec<Owners>>();
+        Ownership {
+            measure: opt.measure,
+            inactive_since,
+            files: owners(files),
+            directories: owners(directories),
+            departed,
+            crossings: Vec::new(),
+        }
+    }
+
+    /// The owners of a file, or of a directory if there is no such file, e.g. when files are grouped by directory.
+    pub fn owners_of(&self, name: &str) -> Option<&Owners> {
+        find_owners(&self.files, name).or_else(|| find_owners(&self.directories, name))
+    }
+
+    /// Flags the pairs co-changing with at least the given probability whose main owners differ,
+    /// most probable first.
+    pub fn flag_crossings(&mut self, cc: &CoChanges, min_probability: f64) {
+        let probs = &cc.probs;
+        let mut crossings = Vec::new();
+        for (i, j, probability) in cc.coupled_pairs(min_probability) {
+            let owner = |k: usize| self.owners_of(&probs.row_names[k]).and_then(|o| o.owner()).map(|o| o.0.clone());
+            if let (Some(a), Some(b)) = (owner(i), owner(j)) {
+                if a != b {
+                    crossings.push(CrossingCoupling {
+                        files: (probs.row_names[i].to_string(), probs.row_names[j].to_string()),
+                        owners: (a, b),
+                        probability,
+                    });
+                }
+            }
+        }
+        crossings.sort_by(|x, y| y.probability.total_cmp(&x.probability).then(x.files.cmp(&y.files)));
+        self.crossings = crossings;
+    }
+}
+
+fn find_owners<'a>(entries: &'a [Owners], name: &str) -> Option<&'a Owners> {
+    entries.binary_search_by(|o| o.name.as_str().cmp(name))
+        .ok()
+        .map(|i| &entries[i])
+}
+
+/// The directories containing a file, innermost first, ending with `.` for the repository root.
+pub(crate) fn directories_of(file: &str) -> Vec<String> {
+    let path = file_of(file);
+    let mut dirs = Vec::new();
+    let mut rest = path;
+    while let Some((dir, _)) = rest.rsplit_once('/') {
+        dirs.push(dir.to_string());
+        rest = dir;
+    }
+    dirs.push(".".to_string());
+    dirs
+}
+
+impl Display for Ownership {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Ownership by {}, authors inactive since {}: {:?}", self.measure, self.inactive_since.format("%Y-%m-%d"), self.departed)?;
+        if let Some(root) = self.directories.iter().find(|d| d.name == ".") {
+            writeln!(f, "Repository bus factor: {}", root.bus_factor)?;
+        }
+        let single_owner = self.files.iter().filter(|o| o.bus_factor == 1).count();
+        writeln!(f, "Files with a bus factor of 1: {} of {}", single_owner, self.files.len())?;
+        let lost = self.files.iter()
+            .filter(|o| o.knowledge_loss > 0.0)
+            .sorted_by(|x, y| y.knowledge_loss.total_cmp(&x.knowledge_loss))
+            .take(DISPLAYED_ENTRIES)
+            .collect::<Vec<&Owners>>();
+        if !lost.is_empty() {
+            writeln!(f, "Knowledge loss   File")?;
+            for o in lost {
+                writeln!(f, "          {:0.2}   {}", o.knowledge_loss, o.name)?;
+            }
+        }
+        if !self.crossings.is_empty() {
+            writeln!(f, "Coupling across owners ({} pairs)", self.crossings.len())?;
+            for c in self.crossings.iter().take(DISPLAYED_ENTRIES) {
+                writeln!(f, "          {:0.2}   {} ({}) <-> {} ({})", c.probability, c.files.0, c.owners.0, c.files.1, c.owners.1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use bettergit::diffs_with_churn;
+    use cochanges::{CCMatrix, CoChanges, CoChangesOpt};
+    use ownership::{directories_of, Ownership, OwnershipMeasure, OwnershipOpt};
+
+    #[test]
+    fn test_ownership() {
+        // (day, author, file, churn); Carol left long before the others' last commits
+        let changes = [
+            (0, "Carol", "src/db/store.rs", 100), (1, "Carol", "src/db/store.rs", 10),
+            (300, "Alice", "src/db/store.rs", 5), (300, "Alice", "src/api/routes.rs", 20),
+            (301, "Bob", "src/api/routes.rs", 1), (302, "Bob", "src/api/routes.rs", 1),
+            (303, "Bob", "src/api/routes.rs", 1),
+        ];
+        let diffs = diffs_with_churn(&changes);
+        let mut ownership = Ownership::from_diffs(&diffs, &OwnershipOpt::default());
+        assert_eq!(vec!["Carol"], ownership.departed);
+        let store = ownership.owners_of("src/db/store.rs").unwrap();
+        assert_eq!(("Carol".to_string(), 2.0 / 3.0), store.shares[0]);
+        assert_eq!(1, store.bus_factor);
+        assert!((store.knowledge_loss - 2.0 / 3.0).abs() < 1e-9);
+        let routes = ownership.owners_of("src/api/routes.rs").unwrap();
+        assert_eq!(Some(&("Bob".to_string(), 0.75)), routes.owner());
+        assert_eq!(0.0, routes.knowledge_loss);
+        // Alice, Bob and Carol contributed 2, 3 and 2 commits to the repository
+        assert_eq!(2, ownership.owners_of(".").unwrap().bus_factor);
+
+        let by_churn = Ownership::from_diffs(&diffs, &OwnershipOpt { measure: OwnershipMeasure::Churn, ..OwnershipOpt::default() });
+        assert_eq!(Some(&("Alice".to_string(), 20.0 / 23.0)), by_churn.owners_of("src/api/routes.rs").unwrap().owner());
+
+        let names = vec![Rc::new("src/api/routes.rs".to_string()), Rc::new("src/db/store.rs".to_string())];
+        let mut probs = CCMatrix::new(names.clone(), names.clone(), Some("impacted"), Some("changing"));
+        probs.matrix[[0, 1]] = 0.8;
+        probs.matrix[[1, 0]] = 0.4;
+        let freqs = CCMatrix::new(names.clone(), names, Some("impacted"), Some("changed"));
+        let cc = CoChanges { freqs, probs, opts: CoChangesOpt::default(), components: Default::default() };
+        ownership.flag_crossings(&cc, 0.5);
+        assert_eq!(1, ownership.crossings.len());
+        assert_eq!(("Bob".to_string(), "Carol".to_string()), ownership.crossings[0].owners);
+        ownership.flag_crossings(&cc, 0.9);
+        assert!(ownership.crossings.is_empty());
+
+        assert_eq!(vec!["src/api", "src", "."], directories_of("src/api/routes.rs::Router::get"));
+    }
+}
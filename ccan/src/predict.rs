@@ -1,21 +1,37 @@
-use std::fmt::{Display, Formatter};
+use std::fmt::{Display, Formatter, Write};
+use std::rc::Rc;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Days, Utc};
 use itertools::Itertools;
 use log::debug;
 use ndarray::s;
+use serde::Deserialize;
 
 use changes::Changes;
 use cochanges::CoChanges;
+use config::{deserialize_since, deserialize_until};
 
 use crate::model::ModelTypes;
 
-#[derive(Clone)]
+#[derive(Clone, Deserialize)]
+#[serde(default)]
 pub struct PredictionOpt {
     pub skip: bool,
+    #[serde(rename = "since", deserialize_with = "deserialize_since")]
     pub since_changes: DateTime<Utc>,
+    #[serde(rename = "until", deserialize_with = "deserialize_until")]
     pub until_changes: DateTime<Utc>,
+    #[serde(skip)]
     pub algorithm: ModelTypes,
+    pub changed_files: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MarkdownOpt {
+    pub title: String,
+    pub min_probability: f64,
+    pub max_files: usize,
+    pub details: bool,
 }
 
 pub type CRVector = Vec<(String, f64)>;
@@ -25,7 +41,7 @@ pub struct RippleChangeProbabilities {
 }
 
 impl RippleChangeProbabilities {
-    fn new() -> RippleChangeProbabilities {
+    pub fn new() -> RippleChangeProbabilities {
         RippleChangeProbabilities {
             ripples: Vec::new(),
             changing_files: Vec::new(),
@@ -40,6 +56,9 @@ impl RippleChangeProbabilities {
         if opt.skip {
             return RippleChangeProbabilities::new();
         }
+        if !opt.changed_files.is_empty() {
+            return RippleChangeProbabilities::from_files(cc, opt.changed_files.clone(), opt);
+        }
         let indices = changes
             .freqs
             .col_names
@@ -60,6 +79,14 @@ impl RippleChangeProbabilities {
             }
         }
 
+        RippleChangeProbabilities::from_files(cc, changing_files, opt)
+    }
+
+    pub fn from_files(
+        cc: &CoChanges,
+        changing_files: Vec<String>,
+        opt: &PredictionOpt,
+    ) -> RippleChangeProbabilities {
         let model = opt.algorithm.get_model();
         debug!(
             "Calculating ripple change probability from {} files using '{}' algorithm",
@@ -73,9 +100,109 @@ impl RippleChangeProbabilities {
         }
     }
 
+    /// Sets the probability of the files that `keep` rejects, e.g. files deleted since, to zero.
+    /// The files are kept so that the probabilities stay aligned with the co-change files.
+    pub fn clear_files<F: Fn(&str) -> bool>(&mut self, keep: F) {
+        self.ripples.iter_mut()
+            .filter(|(file, _)| !keep(file))
+            .for_each(|r| r.1 = 0.0);
+    }
+
     pub fn get_probabilities(&self) -> Vec<f64> {
         self.ripples.iter().map(|r| r.1).collect()
     }
+
+    /// Renders the files that are likely to change with `in_change` but are not part of it,
+    /// e.g. to be posted as a pull request comment.
+    pub fn to_markdown(&self, cc: &CoChanges, in_change: &[String], opt: &MarkdownOpt) -> String {
+        let missing = self
+            .ripples
+            .iter()
+            .filter(|p| p.1 >= opt.min_probability && !in_change.contains(&p.0))
+            .sorted_by(|x, y| y.1.total_cmp(&x.1))
+            .take(opt.max_files)
+            .collect::<Vec<&(String, f64)>>();
+        let touched = in_change
+            .iter()
+            .filter_map(|f| cc.freqs.index_of_col(&Rc::new(f.clone())).map(|j| (f, j)))
+            .collect::<Vec<(&String, usize)>>();
+
+        let mut md = String::new();
+        writeln!(md, "### {}\n", opt.title).unwrap();
+        if missing.is_empty() {
+            writeln!(
+                md,
+                "No files usually changing with this change are missing (threshold {:.2}).",
+                opt.min_probability
+            )
+            .unwrap();
+            return md;
+        }
+        writeln!(md, "These files usually change together with the ones in this change, but are not part of it.\n").unwrap();
+        writeln!(md, "| File | Probability | Co-changes |").unwrap();
+        writeln!(md, "|------|------------:|-----------:|").unwrap();
+        for (file, p) in missing.iter() {
+            let co_changes: f64 = cc.freqs.index_of_row(&Rc::new(file.clone()))
+                .map(|i| touched.iter().map(|(_, j)| cc.freqs.matrix[[i, *j]]).sum())
+                .unwrap_or(0.0);
+            writeln!(md, "| {} | {:.2} | {} |", md_code(file), p, co_changes.round()).unwrap();
+        }
+        if opt.details {
+            writeln!(md, "\n<details>\n<summary>Details</summary>\n").unwrap();
+            writeln!(md, "| Missing file | Changes with | Probability | Co-changes |").unwrap();
+            writeln!(md, "|--------------|--------------|------------:|-----------:|").unwrap();
+            for (file, _) in missing.iter() {
+                let i = match cc.probs.index_of_row(&Rc::new(file.clone())) {
+                    Some(i) => i,
+                    None => continue,
+                };
+                let pairs = touched
+                    .iter()
+                    .map(|(t, j)| (t, cc.probs.matrix[[i, *j]], cc.freqs.matrix[[i, *j]]))
+                    .filter(|(_, p, _)| *p > 0.0)
+                    .sorted_by(|x, y| y.1.total_cmp(&x.1));
+                for (t, p, f) in pairs {
+                    writeln!(md, "| {} | {} | {:.2} | {} |", md_code(file), md_code(t), p, f.round()).unwrap();
+                }
+            }
+            writeln!(md, "\n</details>").unwrap();
+        }
+        md
+    }
+}
+
+impl Default for RippleChangeProbabilities {
+    fn default() -> Self {
+        RippleChangeProbabilities::new()
+    }
+}
+
+impl Default for PredictionOpt {
+    fn default() -> Self {
+        let today = Utc::now().date_naive();
+        PredictionOpt {
+            skip: false,
+            since_changes: (today - Days::new(30)).and_hms_opt(0, 0, 0).unwrap().and_utc(),
+            until_changes: (today + Days::new(1)).and_hms_opt(23, 59, 59).unwrap().and_utc(),
+            algorithm: ModelTypes::Naive,
+            changed_files: Vec::new(),
+        }
+    }
+}
+
+impl Default for MarkdownOpt {
+    fn default() -> Self {
+        MarkdownOpt {
+            title: "Files that usually change with this change".to_string(),
+            min_probability: 0.1,
+            max_files: 10,
+            details: true,
+        }
+    }
+}
+
+fn md_code(s: &str) -> String {
+    format!("`{}`", s.replace('|', "\\|"))
 }
 
 impl Display for RippleChangeProbabilities {
@@ -103,3 +230,43 @@ pub trait RippleChangePredictor {
         opts: &PredictionOpt,
     ) -> CRVector;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use cochanges::{CCMatrix, CoChanges, CoChangesOpt};
+    use model::ModelTypes;
+    use predict::{MarkdownOpt, RippleChangeProbabilities};
+
+    #[test]
+    fn test_markdown_lists_missing_files_only() {
+        let names = ["a.rs", "b.rs", "c|d.rs"].iter().map(|s| Rc::new(s.to_string())).collect::<Vec<Rc<String>>>();
+        let mut freqs = CCMatrix::new(names.clone(), names.clone(), None, None);
+        let mut probs = CCMatrix::new(names.clone(), names.clone(), None, None);
+        freqs.matrix[[1, 0]] = 4.0;
+        freqs.matrix[[2, 0]] = 2.0;
+        probs.matrix[[1, 0]] = 0.8;
+        probs.matrix[[2, 0]] = 0.4;
+        let opts = CoChangesOpt { changes_min: 0, freq_min: 0, algorithm: ModelTypes::Bayes };
+        let cc = CoChanges { freqs, probs, opts, components: Default::default() };
+        let ripples = RippleChangeProbabilities {
+            changing_files: vec!["a.rs".to_string(), "b.rs".to_string()],
+            ripples: vec![("a.rs".to_string(), 0.0), ("b.rs".to_string(), 0.8), ("c|d.rs".to_string(), 0.4)],
+        };
+        let md = ripples.to_markdown(&cc, &["a.rs".to_string(), "b.rs".to_string()], &MarkdownOpt::default());
+        assert!(!md.contains("| `b.rs` | 0.80"));
+        assert!(md.contains("| `c\\|d.rs` | 0.40 | 2 |"));
+        assert!(md.contains("<details>"));
+    }
+
+    #[test]
+    fn test_cleared_files_keep_their_position() {
+        let mut ripples = RippleChangeProbabilities {
+            changing_files: vec!["a.rs".to_string()],
+            ripples: vec![("a.rs".to_string(), 0.2), ("b.rs".to_string(), 0.8), ("c.rs".to_string(), 0.4)],
+        };
+        ripples.clear_files(|f| f != "b.rs");
+        assert_eq!(vec![0.2, 0.0, 0.4], ripples.get_probabilities());
+    }
+}
@@ -0,0 +1,344 @@
+use std::io::Write;
+use std::rc::Rc;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+
+use graph::{directory_of, xml_escape};
+use {AnalysisOutput, Options};
+
+#[derive(Clone, Debug)]
+pub struct ReportOpt {
+    pub title: String,
+    pub max_pairs: usize,
+    pub max_files: usize,
+}
+
+struct CoupledPair<'a> {
+    changing: &'a str,
+    impacted: &'a str,
+    prob: f64,
+    freq: f64,
+}
+
+impl Default for ReportOpt {
+    fn default() -> Self {
+        ReportOpt {
+            title: "Co-change report".to_string(),
+            max_pairs: 200,
+            max_files: 50,
+        }
+    }
+}
+
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.6em; } h2 { font-size: 1.2em; margin-top: 2em; border-bottom: 1px solid #ccc; }
+table { border-collapse: collapse; font-size: 0.85em; }
+th, td { padding: 2px 8px; text-align: left; border-bottom: 1px solid #eee; }
+th.sortable { cursor: pointer; user-select: none; } th.sortable:hover { background: #eef; }
+td.num { text-align: right; font-family: monospace; }
+nav a { margin-right: 1em; }
+.heatmap text { font-size: 10px; font-family: monospace; }
+.timeline line { stroke: #ccc; } .timeline circle { fill: #c0392b; }
+details { margin: 4px 0; } summary { cursor: pointer; }
+"#;
+
+const SCRIPT: &str = r#"
+document.querySelectorAll("table.sortable").forEach(function (table) {
+  table.querySelectorAll("th.sortable").forEach(function (th, col) {
+    th.addEventListener("click", function () {
+      var body = table.tBodies[0];
+      var asc = th.dataset.order !== "asc";
+      th.dataset.order = asc ? "asc" : "desc";
+      var rows = Array.prototype.slice.call(body.rows);
+      rows.sort(function (a, b) {
+        var x = a.cells[col].dataset.value || a.cells[col].textContent;
+        var y = b.cells[col].dataset.value || b.cells[col].textContent;
+        var nx = parseFloat(x), ny = parseFloat(y);
+        var cmp = (isNaN(nx) || isNaN(ny)) ? x.localeCompare(y) : nx - ny;
+        return asc ? cmp : -cmp;
+      });
+      rows.forEach(function (r) { body.appendChild(r); });
+    });
+  });
+});
+"#;
+
+/// Writes a single, self-contained HTML page (no external scripts or styles) describing the analysis.
+pub fn write_html_report(
+    w: &mut dyn Write,
+    output: &AnalysisOutput,
+    opts: Option<&Options>,
+    report_opt: &ReportOpt,
+) -> Result<()> {
+    let title = xml_escape(&report_opt.title);
+    writeln!(w, "<!DOCTYPE html>")?;
+    writeln!(w, "<html lang=\"en\"><head><meta charset=\"utf-8\">")?;
+    writeln!(w, "<title>{title}</title><style>{STYLE}</style></head><body>")?;
+    writeln!(w, "<h1>{title}</h1>")?;
+    writeln!(w, "<nav><a href=\"#summary\">Summary</a><a href=\"#pairs\">Coupled pairs</a><a href=\"#heatmap\">Heatmap</a><a href=\"#timelines\">Timelines</a><a href=\"#ripples\">Predictions</a></nav>")?;
+    write_summary(w, output, opts)?;
+    let pairs = coupled_pairs(output, report_opt.max_pairs);
+    write_pairs(w, &pairs)?;
+    let files = top_files(output, report_opt.max_files);
+    write_heatmap(w, output, &files)?;
+    write_timelines(w, output, &files)?;
+    write_ripples(w, output)?;
+    writeln!(w, "<script>{SCRIPT}</script>")?;
+    writeln!(w, "</body></html>")?;
+    Ok(())
+}
+
+fn write_summary(w: &mut dyn Write, output: &AnalysisOutput, opts: Option<&Options>) -> Result<()> {
+    let mut rows = match opts {
+        Some(opts) => options_summary(opts),
+        None => Vec::new(),
+    };
+    rows.extend([
+        ("Mined files", output.changes.freqs.row_names.len().to_string()),
+        ("Mined transactions", output.changes.freqs.col_names.len().to_string()),
+        ("Analysed files", output.co_changes.freqs.row_names.len().to_string()),
+    ]);
+    writeln!(w, "<h2 id=\"summary\">Summary</h2><table>")?;
+    for (name, value) in rows.iter() {
+        writeln!(w, "<tr><th>{name}</th><td>{}</td></tr>", xml_escape(value))?;
+    }
+    writeln!(w, "</table>")?;
+    Ok(())
+}
+
+fn options_summary(opts: &Options) -> Vec<(&'static str, String)> {
+    let git = &opts.git_opts;
+    vec![
+        ("Repository", opts.repository.clone()),
+        ("Branch", git.commit_filters.branch.clone()),
+        ("Since", date(&git.commit_filters.since)),
+        ("Until", date(&git.commit_filters.until)),
+        ("Date binning", git.commit_filters.binning.to_string()),
+        ("Transactions", git.commit_filters.transactions.to_string()),
+        ("Granularity", git.granularity.to_string()),
+        ("Churn weighting", git.churn.to_string()),
+        ("Excluded changes", git.exclude_changes.iter().join(", ")),
+        ("Components", opts.components.to_string()),
+        ("Include paths", git.file_filters.include_paths.to_string()),
+        ("Exclude paths", git.file_filters.exclude_paths.to_string()),
+        ("Algorithm", opts.cc_opts.algorithm.to_string()),
+        ("Minimum changes", opts.cc_opts.changes_min.to_string()),
+        ("Minimum co-change frequency", opts.cc_opts.freq_min.to_string()),
+        ("Prediction", if opts.pred_opts.skip {
+            "skipped".to_string()
+        } else {
+            format!("{} to {} ({})", date(&opts.pred_opts.since_changes), date(&opts.pred_opts.until_changes), opts.pred_opts.algorithm)
+        }),
+    ]
+}
+
+fn coupled_pairs(output: &AnalysisOutput, max_pairs: usize) -> Vec<CoupledPair<'_>> {
+    let cc = &output.co_changes;
+    cc.probs.matrix.indexed_iter()
+        .filter(|((i, j), p)| i != j && **p > 0.0)
+        .map(|((i, j), p)| CoupledPair {
+            changing: cc.probs.col_names[j].as_str(),
+            impacted: cc.probs.row_names[i].as_str(),
+            prob: *p,
+            freq: cc.freqs.matrix[[i, j]],
+        })
+        .sorted_by(|x, y| y.prob.total_cmp(&x.prob))
+        .take(max_pairs)
+        .collect()
+}
+
+fn write_pairs(w: &mut dyn Write, pairs: &[CoupledPair]) -> Result<()> {
+    writeln!(w, "<h2 id=\"pairs\">Strongest coupled pairs</h2>")?;
+    writeln!(w, "<p>Click a column header to sort. Probability is P(impacted | changing).</p>")?;
+    writeln!(w, "<table class=\"sortable\"><thead><tr>")?;
+    writeln!(w, "<th class=\"sortable\">Changing file</th><th class=\"sortable\">Impacted file</th><th class=\"sortable\">Probability</th><th class=\"sortable\">Co-change frequency</th>")?;
+    writeln!(w, "</tr></thead><tbody>")?;
+    for pair in pairs {
+        writeln!(
+            w,
+            "<tr><td>{}</td><td>{}</td><td class=\"num\">{:.3}</td><td class=\"num\">{:.2}</td></tr>",
+            xml_escape(pair.changing), xml_escape(pair.impacted), pair.prob, pair.freq
+        )?;
+    }
+    writeln!(w, "</tbody></table>")?;
+    Ok(())
+}
+
+/// Most changed files among the analysed ones, ordered by directory and name.
+fn top_files(output: &AnalysisOutput, max_files: usize) -> Vec<usize> {
+    let cc = &output.co_changes.probs;
+    let changes = &output.changes;
+    (0..cc.row_names.len())
+        .map(|i| {
+            let freq = changes.freqs.index_of_row(&cc.row_names[i])
+                .map(|r| changes.c_freq[r])
+                .unwrap_or(0);
+            (i, freq)
+        })
+        .sorted_by(|x, y| y.1.cmp(&x.1))
+        .take(max_files)
+        .map(|(i, _)| i)
+        .sorted_by_key(|i| (directory_of(&cc.row_names[*i]), cc.row_names[*i].clone()))
+        .collect()
+}
+
+fn write_heatmap(w: &mut dyn Write, output: &AnalysisOutput, files: &[usize]) -> Result<()> {
+    let cc = &output.co_changes.probs;
+    let cell = 14;
+    let label_width = 8 * files.iter().map(|i| cc.row_names[*i].len()).max().unwrap_or(0) + 10;
+    let size = cell * files.len();
+    writeln!(w, "<h2 id=\"heatmap\">Co-change probability heatmap</h2>")?;
+    writeln!(w, "<p>Rows are impacted files, columns are changing files, both ordered by directory. Hover a cell for details.</p>")?;
+    writeln!(
+        w,
+        "<svg class=\"heatmap\" width=\"{}\" height=\"{}\" xmlns=\"http://www.w3.org/2000/svg\">",
+        label_width + size + 10, label_width + size + 10
+    )?;
+    for (r, i) in files.iter().enumerate() {
+        let name = xml_escape(&cc.row_names[*i]);
+        let offset = label_width + r * cell + cell - 3;
+        writeln!(w, "<text x=\"{}\" y=\"{offset}\" text-anchor=\"end\">{name}</text>", label_width - 4)?;
+        writeln!(w, "<text transform=\"translate({offset},{}) rotate(-90)\">{name}</text>", label_width - 4)?;
+    }
+    for (r, i) in files.iter().enumerate() {
+        for (c, j) in files.iter().enumerate() {
+            let p = cc.matrix[[*i, *j]];
+            let shade = 255 - (p.clamp(0.0, 1.0) * 215.0) as u8;
+            writeln!(
+                w,
+                "<rect x=\"{}\" y=\"{}\" width=\"{cell}\" height=\"{cell}\" fill=\"rgb(255,{shade},{shade})\" stroke=\"#f4f4f4\"><title>{} | {}: {:.3}</title></rect>",
+                label_width + c * cell, label_width + r * cell,
+                xml_escape(&cc.row_names[*i]), xml_escape(&cc.col_names[*j]), p
+            )?;
+        }
+    }
+    writeln!(w, "</svg>")?;
+    Ok(())
+}
+
+fn write_timelines(w: &mut dyn Write, output: &AnalysisOutput, files: &[usize]) -> Result<()> {
+    let changes = &output.changes.freqs;
+    let names = &output.co_changes.probs.row_names;
+    writeln!(w, "<h2 id=\"timelines\">Change timelines</h2>")?;
+    let (first, last) = match (changes.col_names.iter().min(), changes.col_names.iter().max()) {
+        (Some(f), Some(l)) => (*f, *l),
+        _ => return Ok(()),
+    };
+    writeln!(w, "<p>Each dot is a transaction changing the file, from {} to {}.</p>", date(&first), date(&last))?;
+    let width = 600.0;
+    let span = ((last - first).num_seconds() as f64).max(1.0);
+    writeln!(w, "<table>")?;
+    for i in files {
+        let row = match changes.index_of_row(&names[*i]) {
+            Some(r) => r,
+            None => continue,
+        };
+        write!(w, "<tr><td>{}</td><td><svg class=\"timeline\" width=\"{}\" height=\"12\" xmlns=\"http://www.w3.org/2000/svg\">", xml_escape(&names[*i]), width + 8.0)?;
+        write!(w, "<line x1=\"4\" y1=\"6\" x2=\"{}\" y2=\"6\"/>", width + 4.0)?;
+        for (c, x) in changes.matrix.row(row).iter().enumerate() {
+            if *x <= 0.0 {
+                continue;
+            }
+            let d = changes.col_names[c];
+            let pos = 4.0 + width * ((d - first).num_seconds() as f64) / span;
+            write!(w, "<circle cx=\"{pos:.1}\" cy=\"6\" r=\"3\"><title>{}</title></circle>", date(&d))?;
+        }
+        writeln!(w, "</svg></td></tr>")?;
+    }
+    writeln!(w, "</table>")?;
+    Ok(())
+}
+
+fn write_ripples(w: &mut dyn Write, output: &AnalysisOutput) -> Result<()> {
+    let ripples = &output.ripples;
+    let probs = &output.co_changes.probs;
+    writeln!(w, "<h2 id=\"ripples\">Ripple change predictions</h2>")?;
+    if ripples.changing_files.is_empty() {
+        writeln!(w, "<p>No prediction was computed for this analysis.</p>")?;
+        return Ok(());
+    }
+    writeln!(w, "<p>Files changed in the prediction period: {}.</p>", ripples.changing_files.iter().map(|f| xml_escape(f)).join(", "))?;
+    writeln!(w, "<table class=\"sortable\"><thead><tr><th class=\"sortable\">File</th><th class=\"sortable\">Probability</th><th>Explanation</th></tr></thead><tbody>")?;
+    let predictions = ripples.ripples.iter()
+        .filter(|p| p.1 >= 1e-2)
+        .sorted_by(|x, y| y.1.total_cmp(&x.1));
+    for (file, p) in predictions {
+        let because = probs.index_of_row(&Rc::new(file.clone()))
+            .map(|i| {
+                ripples.changing_files.iter()
+                    .filter_map(|c| probs.index_of_col(&Rc::new(c.clone())).map(|j| (c, probs.matrix[[i, j]])))
+                    .filter(|(_, p)| *p > 0.0)
+                    .sorted_by(|x, y| y.1.total_cmp(&x.1))
+                    .map(|(c, p)| format!("<li>{} ({:.3})</li>", xml_escape(c), p))
+                    .join("")
+            })
+            .unwrap_or_default();
+        writeln!(
+            w,
+            "<tr><td>{}</td><td class=\"num\">{:.3}</td><td><details><summary>changes with</summary><ul>{because}</ul></details></td></tr>",
+            xml_escape(file), p
+        )?;
+    }
+    writeln!(w, "</tbody></table>")?;
+    Ok(())
+}
+
+fn date(d: &DateTime<Utc>) -> String {
+    d.format("%Y-%m-%d").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use bettergit::diffs_of;
+    use changes::Changes;
+    use cochanges::{CoChanges, CoChangesOpt};
+    use model::ModelTypes;
+    use predict::RippleChangeProbabilities;
+    use report::{write_html_report, ReportOpt};
+    use {AnalysisOutput, Options};
+
+    fn report(ripples: RippleChangeProbabilities) -> String {
+        let changes = Changes::from_diffs(diffs_of(&[
+            (0, "Alice", "src/<gen>&a.rs"), (0, "Alice", "src/b.rs"),
+            (1, "Alice", "src/<gen>&a.rs"), (1, "Alice", "src/b.rs"), (2, "Alice", "src/b.rs"),
+        ]));
+        let co_changes = CoChanges::from_changes(&changes, &CoChangesOpt { changes_min: 0, freq_min: 0, algorithm: ModelTypes::Bayes });
+        let output = AnalysisOutput {
+            changes,
+            co_changes,
+            ripples,
+            hotspots: None,
+            ownership: None,
+            coordination: None,
+            communities: None,
+            violations: None,
+            hidden_dependencies: None,
+        };
+        let mut html = Vec::new();
+        write_html_report(&mut html, &output, Some(&Options::default()), &ReportOpt::default()).unwrap();
+        String::from_utf8(html).unwrap()
+    }
+
+    #[test]
+    fn test_html_report() {
+        let html = report(RippleChangeProbabilities {
+            changing_files: vec!["src/b.rs".to_string()],
+            ripples: vec![("src/<gen>&a.rs".to_string(), 1.0), ("src/b.rs".to_string(), 0.0)],
+        });
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert_eq!(1, html.matches("<html").count());
+        assert!(html.trim_end().ends_with("</html>"));
+        for external in ["<link", "src=", "href=\"http", "url(", "@import"] {
+            assert!(!html.contains(external), "references an external resource: {}", external);
+        }
+        assert!(!html.contains("<gen>"));
+        assert!(html.contains("src/&lt;gen&gt;&amp;a.rs"));
+
+        // export --report has no predictions
+        let html = report(RippleChangeProbabilities::new());
+        assert!(html.trim_end().ends_with("</html>"));
+    }
+}
@@ -0,0 +1,275 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use anyhow::bail;
+use serde::Deserialize;
+use tree_sitter::{Language, Node, Parser, Tree};
+use tree_sitter_java;
+use tree_sitter_python;
+use tree_sitter_rust;
+use tree_sitter_typescript;
+
+/// Whether the rows of `Changes` are files, or the functions, methods and types changed in them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    #[default]
+    Files,
+    Symbols,
+}
+
+impl FromStr for Granularity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "files" => Ok(Granularity::Files),
+            "symbols" => Ok(Granularity::Symbols),
+            _ => bail!("cannot parse Granularity from {}", s)
+        }
+    }
+}
+
+impl Display for Granularity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Granularity::Files => "files",
+            Granularity::Symbols => "symbols",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SourceLanguage {
+    Rust,
+    Java,
+    Python,
+    TypeScript,
+    Tsx,
+}
+
+impl SourceLanguage {
+    fn of(path: &str) -> Option<SourceLanguage> {
+        let extension = path.rsplit_once('.').map(|(_, e)| e.to_lowercase())?;
+        match extension.as_str() {
+            "rs" => Some(SourceLanguage::Rust),
+            "java" => Some(SourceLanguage::Java),
+            "py" => Some(SourceLanguage::Python),
+            "ts" | "mts" | "cts" => Some(SourceLanguage::TypeScript),
+            "tsx" | "js" | "jsx" | "mjs" | "cjs" => Some(SourceLanguage::Tsx),
+            _ => None,
+        }
+    }
+
+    fn grammar(&self) -> Language {
+        match self {
+            SourceLanguage::Rust => tree_sitter_rust::LANGUAGE.into(),
+            SourceLanguage::Java => tree_sitter_java::LANGUAGE.into(),
+            SourceLanguage::Python => tree_sitter_python::LANGUAGE.into(),
+            SourceLanguage::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            SourceLanguage::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+        }
+    }
+
+    /// The name of the type or function a node defines, if it defines one.
+    fn definition_name(&self, node: &Node, source: &[u8]) -> Option<String> {
+        let name_field = match (self, node.kind()) {
+            (SourceLanguage::Rust, "impl_item") => "type",
+            (SourceLanguage::Rust, "function_item" | "function_signature_item" | "struct_item" | "enum_item"
+                | "union_item" | "trait_item" | "mod_item" | "macro_definition") => "name",
+            (SourceLanguage::Java, "class_declaration" | "interface_declaration" | "enum_declaration"
+                | "record_declaration" | "annotation_type_declaration" | "method_declaration"
+                | "constructor_declaration") => "name",
+            (SourceLanguage::Python, "class_definition" | "function_definition") => "name",
+            (SourceLanguage::TypeScript | SourceLanguage::Tsx, "class_declaration" | "abstract_class_declaration"
+                | "interface_declaration" | "enum_declaration" | "function_declaration"
+                | "generator_function_declaration" | "method_definition" | "abstract_method_signature"
+                | "internal_module") => "name",
+            (SourceLanguage::TypeScript | SourceLanguage::Tsx, "variable_declarator") => {
+                let value = node.child_by_field_name("value")?;
+                if !matches!(value.kind(), "arrow_function" | "function_expression" | "class") {
+                    return None;
+                }
+                "name"
+            }
+            _ => return None,
+        };
+        let name = node.child_by_field_name(name_field)?;
+        // generic types such as `impl<T> Foo<T>` are named after the type only
+        let name = match name.child_by_field_name("type") {
+            Some(inner) if name.kind() == "generic_type" => inner,
+            _ => name,
+        };
+        name.utf8_text(source).ok().map(|s| s.to_string())
+    }
+
+    /// Whether a node adds a path through the code, such as a branch, a loop or a short-circuit operator.
+    fn is_decision(&self, node: &Node) -> bool {
+        let kind = node.kind();
+        let branches: &[&str] = match self {
+            SourceLanguage::Rust => &["if_expression", "while_expression", "for_expression", "match_arm"],
+            SourceLanguage::Java => &[
+                "if_statement", "while_statement", "for_statement", "enhanced_for_statement", "do_statement",
+                "catch_clause", "ternary_expression", "switch_label",
+            ],
+            SourceLanguage::Python => &[
+                "if_statement", "elif_clause", "while_statement", "for_statement", "except_clause",
+                "conditional_expression", "boolean_operator", "for_in_clause", "if_clause", "case_clause",
+            ],
+            SourceLanguage::TypeScript | SourceLanguage::Tsx => &[
+                "if_statement", "while_statement", "for_statement", "for_in_statement", "do_statement",
+                "catch_clause", "ternary_expression", "switch_case",
+            ],
+        };
+        if branches.contains(&kind) {
+            return true;
+        }
+        kind == "binary_expression" && node.child_by_field_name("operator")
+            .is_some_and(|o| matches!(o.kind(), "&&" | "||" | "??"))
+    }
+}
+
+/// A function, method or type definition and the lines it spans, starting at 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// Finds the definitions enclosing changed lines with tree-sitter.
+pub struct SymbolParser {
+    parser: Parser,
+}
+
+impl Default for SymbolParser {
+    fn default() -> Self {
+        SymbolParser::new()
+    }
+}
+
+impl SymbolParser {
+    pub fn new() -> SymbolParser {
+        SymbolParser { parser: Parser::new() }
+    }
+
+    pub fn supports(path: &str) -> bool {
+        SourceLanguage::of(path).is_some()
+    }
+
+    fn parse(&mut self, path: &str, source: &[u8]) -> Option<(SourceLanguage, Tree)> {
+        let language = SourceLanguage::of(path)?;
+        self.parser.set_language(&language.grammar()).ok()?;
+        self.parser.parse(source, None).map(|tree| (language, tree))
+    }
+
+    /// The definitions in the source, outer ones before the ones nested in them.
+    pub fn symbols(&mut self, path: &str, source: &[u8]) -> Vec<Symbol> {
+        let mut symbols = Vec::new();
+        if let Some((language, tree)) = self.parse(path, source) {
+            collect_symbols(language, tree.root_node(), source, &mut Vec::new(), &mut symbols);
+        }
+        symbols
+    }
+
+    /// The cyclomatic complexity of the whole file: one plus its decision points,
+    /// or `None` if the language is not supported.
+    pub fn cyclomatic(&mut self, path: &str, source: &[u8]) -> Option<usize> {
+        let (language, tree) = self.parse(path, source)?;
+        let mut decisions = 0;
+        let mut cursor = tree.walk();
+        let mut visited = false;
+        loop {
+            if !visited && language.is_decision(&cursor.node()) {
+                decisions += 1;
+            }
+            if !visited && cursor.goto_first_child() {
+                continue;
+            }
+            if cursor.goto_next_sibling() {
+                visited = false;
+            } else if cursor.goto_parent() {
+                visited = true;
+            } else {
+                break;
+            }
+        }
+        Some(1 + decisions)
+    }
+}
+
+fn collect_symbols(language: SourceLanguage, node: Node, source: &[u8], scope: &mut Vec<String>, symbols: &mut Vec<Symbol>) {
+    let name = language.definition_name(&node, source);
+    if let Some(name) = &name {
+        scope.push(name.clone());
+        symbols.push(Symbol {
+            name: scope.join("::"),
+            start_line: node.start_position().row + 1,
+            end_line: node.end_position().row + 1,
+        });
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_symbols(language, child, source, scope, symbols);
+    }
+    if name.is_some() {
+        scope.pop();
+    }
+}
+
+/// The file of a row of `Changes`, which is the row itself at file granularity.
+pub fn file_of(row: &str) -> &str {
+    row.split_once("::").map_or(row, |(file, _)| file)
+}
+
+/// The innermost definition spanning the line, if any.
+pub fn enclosing_symbol(symbols: &[Symbol], line: usize) -> Option<&Symbol> {
+    symbols.iter()
+        .filter(|s| s.start_line <= line && line <= s.end_line)
+        .min_by_key(|s| s.end_line - s.start_line)
+}
+
+#[cfg(test)]
+mod tests {
+    use symbols::{enclosing_symbol, file_of, SymbolParser};
+
+    #[test]
+    fn test_rust_symbols() {
+        let source = "use std::fmt;\n\nstruct Point {\n    x: i32,\n}\n\nimpl<T> Shape<T> {\n    fn area(&self) -> f64 {\n        1.0\n    }\n}\n\nfn main() {\n}\n";
+        let symbols = SymbolParser::new().symbols("src/shape.rs", source.as_bytes());
+        let names = symbols.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["Point", "Shape", "Shape::area", "main"], names);
+        assert_eq!(Some("Shape::area"), enclosing_symbol(&symbols, 9).map(|s| s.name.as_str()));
+        assert_eq!(Some("Shape"), enclosing_symbol(&symbols, 11).map(|s| s.name.as_str()));
+        assert_eq!(None, enclosing_symbol(&symbols, 1));
+        assert_eq!("src/shape.rs", file_of("src/shape.rs::Shape::area"));
+        assert_eq!("src/shape.rs", file_of("src/shape.rs"));
+    }
+
+    #[test]
+    fn test_other_languages() {
+        let mut parser = SymbolParser::new();
+        let names = |parser: &mut SymbolParser, path: &str, source: &str| {
+            parser.symbols(path, source.as_bytes()).into_iter().map(|s| s.name).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec!["Api", "Api::Api", "Api::get"],
+            names(&mut parser, "Api.java", "class Api {\n  Api() {}\n  int get() { return 1; }\n}\n")
+        );
+        assert_eq!(
+            vec!["Api", "Api::get", "main"],
+            names(&mut parser, "api.py", "class Api:\n    def get(self):\n        return 1\n\ndef main():\n    pass\n")
+        );
+        assert_eq!(
+            vec!["Api", "Api::get", "handler"],
+            names(&mut parser, "web/api.ts", "class Api {\n  get(): number { return 1; }\n}\nconst handler = () => 1;\n")
+        );
+        assert!(!SymbolParser::supports("README.md"));
+
+        let source = "fn f(x: i32) -> i32 {\n    if x > 0 && x < 10 {\n        return 1;\n    }\n    match x {\n        0 => 0,\n        _ => 2,\n    }\n}\n";
+        assert_eq!(Some(5), parser.cyclomatic("f.rs", source.as_bytes()));
+        assert_eq!(Some(3), parser.cyclomatic("f.py", "def f(x):\n    for y in x:\n        if y:\n            pass\n".as_bytes()));
+        assert_eq!(None, parser.cyclomatic("f.go", b"func f() {}"));
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use serde::Deserialize;
+
+use bettergit::{BetterCommit, CommitFilteringOpt, DateGrouping};
+
+pub const DEFAULT_ISSUE_REGEX: &str = r"[A-Z][A-Z0-9]+-\d+|#\d+";
+pub const DEFAULT_SESSION_GAP: &str = "30m";
+
+/// How commits are merged into the transactions (columns) of `Changes.freqs`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionGrouping {
+    /// Commits in the same date bin, see `DateGrouping`.
+    #[default]
+    Dates,
+    /// Commits mentioning the same issue key, other commits on their own.
+    Issues,
+    /// Consecutive commits by the same author, each within the session gap of the previous one.
+    Sessions,
+}
+
+impl FromStr for TransactionGrouping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dates" => Ok(TransactionGrouping::Dates),
+            "issues" => Ok(TransactionGrouping::Issues),
+            "sessions" => Ok(TransactionGrouping::Sessions),
+            _ => bail!("cannot parse TransactionGrouping from {}", s)
+        }
+    }
+}
+
+impl Display for TransactionGrouping {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            TransactionGrouping::Dates => "dates",
+            TransactionGrouping::Issues => "issues",
+            TransactionGrouping::Sessions => "sessions",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Assigns commits, visited from oldest to newest, to the date identifying their transaction.
+pub(crate) struct TransactionKeys {
+    grouping: TransactionGrouping,
+    binning: DateGrouping,
+    issue_regex: Regex,
+    issues: HashMap<String, DateTime<Utc>>,
+    session_gap: Duration,
+    sessions: HashMap<String, (DateTime<Utc>, DateTime<Utc>)>,
+    used: HashSet<DateTime<Utc>>,
+}
+
+impl TransactionKeys {
+    pub(crate) fn new(filters: &CommitFilteringOpt) -> Result<TransactionKeys> {
+        let issue_regex = Regex::new(&filters.issue_regex)
+            .map_err(|e| anyhow!("invalid issue regex: {}", e))?;
+        Ok(TransactionKeys {
+            grouping: filters.transactions,
+            binning: filters.binning.clone(),
+            issue_regex,
+            issues: HashMap::new(),
+            session_gap: parse_duration(&filters.session_gap)?,
+            sessions: HashMap::new(),
+            used: HashSet::new(),
+        })
+    }
+
+    pub(crate) fn key(&mut self, commit: &BetterCommit, message: &str) -> DateTime<Utc> {
+        match self.grouping {
+            TransactionGrouping::Dates => self.binning.get_group(&commit.when),
+            TransactionGrouping::Issues => match self.issue_regex.find(message) {
+                Some(issue) => match self.issues.get(issue.as_str()) {
+                    Some(key) => *key,
+                    None => {
+                        let key = self.unique(commit.when);
+                        self.issues.insert(issue.as_str().to_string(), key);
+                        key
+                    }
+                },
+                None => self.unique(commit.when),
+            },
+            TransactionGrouping::Sessions => {
+                let author = format!("{} <{}>", commit.author, commit.email).to_lowercase();
+                let key = match self.sessions.get(&author) {
+                    Some((last, key)) if commit.when - *last <= self.session_gap => *key,
+                    _ => self.unique(commit.when),
+                };
+                self.sessions.insert(author, (commit.when, key));
+                key
+            }
+        }
+    }
+
+    /// A key not used by other transactions, so that unrelated commits made at the same time stay apart.
+    fn unique(&mut self, when: DateTime<Utc>) -> DateTime<Utc> {
+        let mut key = when;
+        while self.used.contains(&key) {
+            key += Duration::seconds(1);
+        }
+        self.used.insert(key);
+        key
+    }
+}
+
+/// Parses durations such as `90s`, `30m`, `2h` or `1d`, minutes when the unit is missing.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let amount = s[..split].parse::<i64>()
+        .map_err(|_| anyhow!("invalid duration {}, expected e.g. 30m", s))?;
+    match s[split..].trim() {
+        "s" => Ok(Duration::seconds(amount)),
+        "" | "m" | "min" => Ok(Duration::minutes(amount)),
+        "h" => Ok(Duration::hours(amount)),
+        "d" => Ok(Duration::days(amount)),
+        unit => bail!("invalid duration unit {} in {}, expected s, m, h or d", unit, s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    use bettergit::{BetterCommit, CommitFilteringOpt};
+    use transactions::{parse_duration, TransactionGrouping, TransactionKeys};
+
+    #[test]
+    fn test_issue_transactions() {
+        let start = Utc.with_ymd_and_hms(2023, 5, 1, 9, 0, 0).unwrap();
+        let messages = [
+            "PROJ-1: part 1", "unrelated fix", "PROJ-2: other", "PROJ-1: part 2",
+            "chore: bump", "Fixes #42", "follow-up for #42 and PROJ-2",
+        ];
+        let opts = CommitFilteringOpt {
+            transactions: TransactionGrouping::Issues,
+            ..CommitFilteringOpt::default()
+        };
+        let mut keys = TransactionKeys::new(&opts).unwrap();
+        let mut transactions = HashMap::new();
+        for (i, message) in messages.iter().enumerate() {
+            let commit = BetterCommit {
+                sha1: i.to_string(),
+                author: "Alice".to_string(),
+                email: "alice@example.com".to_string(),
+                // the second commit happens at the same time as the first one
+                when: start + Duration::minutes(i.saturating_sub(1) as i64),
+                summary: message.to_string(),
+            };
+            transactions.entry(keys.key(&commit, message)).or_insert_with(Vec::new).push(i);
+        }
+        let mut transactions = transactions.into_values().collect::<Vec<_>>();
+        transactions.sort();
+        assert_eq!(vec![vec![0, 3], vec![1], vec![2], vec![4], vec![5, 6]], transactions);
+    }
+
+    #[test]
+    fn test_session_transactions() {
+        let start = Utc.with_ymd_and_hms(2023, 5, 1, 9, 0, 0).unwrap();
+        let commits = [("Alice", 0), ("Bob", 5), ("Alice", 20), ("Alice", 45), ("Bob", 50), ("Alice", 120)];
+        let opts = CommitFilteringOpt {
+            transactions: TransactionGrouping::Sessions,
+            session_gap: "30m".to_string(),
+            ..CommitFilteringOpt::default()
+        };
+        let mut keys = TransactionKeys::new(&opts).unwrap();
+        let mut transactions = HashMap::new();
+        for (i, (author, minutes)) in commits.iter().enumerate() {
+            let commit = BetterCommit {
+                sha1: i.to_string(),
+                author: author.to_string(),
+                email: format!("{}@example.com", author.to_lowercase()),
+                when: start + Duration::minutes(*minutes),
+                summary: "change".to_string(),
+            };
+            transactions.entry(keys.key(&commit, "change")).or_insert_with(Vec::new).push(i);
+        }
+        let mut transactions = transactions.into_values().collect::<Vec<_>>();
+        transactions.sort();
+        assert_eq!(vec![vec![0, 2, 3], vec![1], vec![4], vec![5]], transactions);
+
+        assert_eq!(Duration::hours(2), parse_duration("2h").unwrap());
+        assert_eq!(Duration::minutes(45), parse_duration("45").unwrap());
+        assert!(parse_duration("soon").is_err());
+    }
+}